@@ -1,5 +1,6 @@
 #![no_std]
 
+pub mod packet;
 pub mod utils;
 
 #[repr(C)]
@@ -20,11 +21,24 @@ pub struct TrafficStats {
     pub inbound_bytes: u64,
     pub outbound_packets: u64,
     pub outbound_bytes: u64,
+    // 最近一个数据包的内核单调时钟时间戳 (bpf_ktime_get_ns)
+    pub last_seen_ns: u64,
+    // 按包更新的字节速率 EWMA（字节/秒）
+    pub byte_rate_ewma: u64,
+    // 包间隔分布：<1ms, <10ms, <100ms, >=100ms
+    pub gap_buckets: [u64; GAP_BUCKETS],
 }
 
 #[cfg(feature = "user")]
 unsafe impl aya::Pod for TrafficStats {}
 
+// 包间隔直方图的桶数及前三个桶的上界
+pub const GAP_BUCKETS: usize = 4;
+pub const GAP_BUCKET_BOUNDS_NS: [u64; GAP_BUCKETS - 1] = [1_000_000, 10_000_000, 100_000_000];
+
+// 超过该间隔没有新数据包时，EWMA 速率视为 0
+pub const RATE_IDLE_NS: u64 = 1_000_000_000;
+
 impl TrafficStats {
     pub fn new() -> Self {
         Self {
@@ -32,7 +46,46 @@ impl TrafficStats {
             inbound_bytes: 0,
             outbound_packets: 0,
             outbound_bytes: 0,
+            last_seen_ns: 0,
+            byte_rate_ewma: 0,
+            gap_buckets: [0; GAP_BUCKETS],
+        }
+    }
+
+    // 当前字节速率，now_ns 为 CLOCK_MONOTONIC 时间
+    pub fn byte_rate(&self, now_ns: u64) -> u64 {
+        if now_ns.saturating_sub(self.last_seen_ns) >= RATE_IDLE_NS {
+            return 0;
+        }
+        self.byte_rate_ewma
+    }
+
+    // 包间隔不小于 10ms 的比例，用于粗略判断抖动
+    pub fn slow_gap_ratio(&self) -> f64 {
+        let total: u64 = self.gap_buckets.iter().sum();
+        if total == 0 {
+            return 0.0;
+        }
+        (self.gap_buckets[2] + self.gap_buckets[3]) as f64 / total as f64
+    }
+
+    // 把一个数据包记到被统计地址上，addr_is_dest 表示该地址是数据包的目的地址，返回是否计为入站
+    #[inline(always)]
+    pub fn record_packet(
+        &mut self,
+        addr_is_dest: bool,
+        iface_direction: Option<u32>,
+        packet_len: u64,
+    ) -> bool {
+        let inbound = is_inbound(addr_is_dest, iface_direction);
+        if inbound {
+            self.inbound_packets += 1;
+            self.inbound_bytes += packet_len;
+        } else {
+            self.outbound_packets += 1;
+            self.outbound_bytes += packet_len;
         }
+        inbound
     }
 
     pub fn total_packets(&self) -> u64 {
@@ -42,4 +95,242 @@ impl TrafficStats {
     pub fn total_bytes(&self) -> u64 {
         self.inbound_bytes + self.outbound_bytes
     }
+
+    // 出站/入站字节比例，入站为 0 时视为无穷大
+    pub fn outbound_ratio(&self) -> f64 {
+        if self.inbound_bytes == 0 {
+            if self.outbound_bytes == 0 {
+                return 0.0;
+            }
+            return f64::INFINITY;
+        }
+        self.outbound_bytes as f64 / self.inbound_bytes as f64
+    }
+}
+
+// 抓包时每个数据包保留的最大字节数（以太网 + IP + L4 头部）
+pub const CAPTURE_LEN: usize = 128;
+
+// 抓包配置，由用户态写入 CAPTURE_CONFIG 的第 0 项
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct CaptureConfig {
+    pub ipv4_address: u32,
+    // 0 表示匹配任意端口
+    pub port: u16,
+    pub enabled: u8,
+    pub _pad: u8,
+    // 每秒最多写入环形缓冲区的数据包数
+    pub max_per_sec: u32,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for CaptureConfig {}
+
+// 写入环形缓冲区的数据包头部样本
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PacketCapture {
+    pub frame_len: u32,
+    pub captured_len: u32,
+    pub data: [u8; CAPTURE_LEN],
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for PacketCapture {}
+
+// 端口过滤策略，由用户态写入 PORT_FILTER 的第 0 项
+// 未配置端口：只按 TARGET_IP 记录
+pub const PORT_FILTER_NONE: u32 = 0;
+// IP 与端口同时匹配才记录
+pub const PORT_FILTER_AND: u32 = 1;
+// IP 或端口任一匹配即记录
+pub const PORT_FILTER_OR: u32 = 2;
+
+// PORT_RANGES 的槽位数，end 为 0 的槽位表示列表结束
+pub const MAX_PORT_RANGES: usize = 16;
+
+// 端口范围，start 与 end 均包含在内
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PortRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for PortRange {}
+
+// IFACE_DIRECTION 中的方向取值
+pub const DIRECTION_INBOUND: u32 = 1;
+pub const DIRECTION_OUTBOUND: u32 = 2;
+
+// 数据包对被统计地址而言是否为入站：该地址是目的地址（接收方）时为入站，是源地址（发送方）时为出站。
+// 入口接口在 IFACE_DIRECTION 中配置了方向时以接口为准
+#[inline(always)]
+pub fn is_inbound(addr_is_dest: bool, iface_direction: Option<u32>) -> bool {
+    match iface_direction {
+        Some(direction) => direction == DIRECTION_INBOUND,
+        None => addr_is_dest,
+    }
+}
+
+// 诊断计数器，eBPF 中按 CPU 累加，用户态读取时求和
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Diagnostics {
+    // IPv4 分片数据包数（MF 置位或片偏移非 0）
+    pub fragmented_packets: u64,
+    // L4 头部异常的数据包数（UDP 长度 < 8、TCP 数据偏移越界），不计入流量统计
+    pub malformed_packets: u64,
+    // 仅端口匹配的地址数已达 MAX_PORT_ONLY_IPS，未能新建统计条目的数据包数
+    pub port_only_dropped: u64,
+    // TRAFFIC_STATS / PEER_STATS 插入失败（Map 已满）的次数
+    pub insert_failed: u64,
+}
+
+impl Diagnostics {
+    // 累加另一个 CPU 上的计数
+    pub fn add(&mut self, other: &Diagnostics) {
+        self.fragmented_packets += other.fragmented_packets;
+        self.malformed_packets += other.malformed_packets;
+        self.port_only_dropped += other.port_only_dropped;
+        self.insert_failed += other.insert_failed;
+    }
+}
+
+// TARGET_IP 的容量
+pub const MAX_TARGET_IPS: u32 = 1024;
+// TARGET_PORT 中单个端口的容量
+pub const MAX_TARGET_PORTS: u32 = 1024;
+// EXCLUDE_IP 中网段的容量
+pub const MAX_EXCLUDE_NETS: u32 = 1024;
+// OR 策略下仅因端口匹配而统计的非目标地址数上限，超出后新地址不再建立条目，避免挤占目标 IP 的空间
+pub const MAX_PORT_ONLY_IPS: u32 = 1024;
+// TRAFFIC_STATS / PEER_STATS 的容量：目标 IP 与仅端口匹配的地址各占一份
+pub const MAX_STATS_ENTRIES: u32 = MAX_TARGET_IPS + MAX_PORT_ONLY_IPS;
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for Diagnostics {}
+
+// PROTOCOL_STATS 以 IP 协议号为下标
+pub const MAX_PROTOCOLS: u32 = 256;
+
+// 接口级别的单个协议统计，eBPF 中按 CPU 累加，用户态读取时求和
+// 包含接口上全部可解析的 IPv4 数据包，不受 TARGET_IP / TARGET_PORT 限制
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ProtocolStats {
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for ProtocolStats {}
+
+// 字节统计口径
+// IP 总长度 (tot_len)，不含以太网头部与 FCS
+pub const LENGTH_MODE_IP: u32 = 0;
+// XDP 看到的完整帧长度 (data_end - data)，含以太网头部
+pub const LENGTH_MODE_WIRE: u32 = 1;
+
+// 按 IP 总长度推算以太网帧与线路开销时使用的每包字节数
+// 以太网头部（目的 MAC + 源 MAC + 类型）
+pub const ETHERNET_HEADER_LEN: u32 = 14;
+// 帧校验序列，交换机与网卡的字节计数通常包含
+pub const ETHERNET_FCS_LEN: u32 = 4;
+// 前导码与帧起始定界符 (8) 加最小帧间隔 (12)，只占用线路时间，不出现在帧中
+pub const ETHERNET_PREAMBLE_IFG_LEN: u32 = 20;
+
+// 可关闭的统计项，置位表示热路径中跳过对应的更新，TRAFFIC_STATS 的包数与字节数始终更新
+// PEER_STATS 远端地址估算
+pub const STATS_PEERS: u32 = 1 << 0;
+// PROTOCOL_STATS 接口协议统计
+pub const STATS_PROTOCOLS: u32 = 1 << 1;
+// TRAFFIC_STATS 中的 EWMA 速率与包间隔分布
+pub const STATS_TIMING: u32 = 1 << 2;
+
+// 运行时设置，由用户态写入 SETTINGS 的第 0 项
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Settings {
+    pub length_mode: u32,
+    // STATS_* 的组合，默认 0 表示全部开启
+    pub disabled_stats: u32,
+    // 按 length_mode 取得的长度之外，每个数据包额外计入的字节数
+    pub length_overhead: u32,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for Settings {}
+
+// 每个目标 IP 的远端地址 HyperLogLog 寄存器数，标准误差约 1.04 / sqrt(64) ≈ 13%
+pub const PEER_REGISTERS: usize = 64;
+const PEER_INDEX_BITS: u32 = 6;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PeerRegisters {
+    pub registers: [u8; PEER_REGISTERS],
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for PeerRegisters {}
+
+// 远端地址对应的寄存器下标与秩（剩余位前导零个数 + 1）
+#[inline(always)]
+pub fn peer_register(peer: u32) -> (usize, u8) {
+    // murmur3 fmix32，打散相邻地址
+    let mut hash = peer;
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2_ae35);
+    hash ^= hash >> 16;
+
+    let index = (hash >> (32 - PEER_INDEX_BITS)) as usize;
+    let rank = ((hash << PEER_INDEX_BITS) | (1 << (PEER_INDEX_BITS - 1))).leading_zeros() + 1;
+    (index, rank as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_receives_requests_and_sends_responses() {
+        const CLIENT: u32 = 0x0a00_0001;
+        const SERVER: u32 = 0xc0a8_0102;
+        let mut server = TrafficStats::new();
+        let mut client = TrafficStats::new();
+
+        // 一次请求/响应：客户端发送 120 字节请求，服务端回复两个 1500 字节的数据包
+        for (source, dest, len) in [
+            (CLIENT, SERVER, 120),
+            (SERVER, CLIENT, 1500),
+            (SERVER, CLIENT, 1500),
+        ] {
+            // 与 eBPF 相同，源地址与目的地址各记一次
+            for (addr, addr_is_dest) in [(source, false), (dest, true)] {
+                let stats = if addr == SERVER {
+                    &mut server
+                } else {
+                    &mut client
+                };
+                stats.record_packet(addr_is_dest, None, len);
+            }
+        }
+
+        assert_eq!((server.inbound_packets, server.inbound_bytes), (1, 120));
+        assert_eq!((server.outbound_packets, server.outbound_bytes), (2, 3000));
+        assert_eq!((client.inbound_packets, client.inbound_bytes), (2, 3000));
+        assert_eq!((client.outbound_packets, client.outbound_bytes), (1, 120));
+        assert_eq!(server.total_bytes(), client.total_bytes());
+    }
+
+    #[test]
+    fn iface_direction_overrides_address_position() {
+        assert!(is_inbound(false, Some(DIRECTION_INBOUND)));
+        assert!(!is_inbound(true, Some(DIRECTION_OUTBOUND)));
+    }
 }
@@ -0,0 +1,395 @@
+// 数据包头部解析，不依赖 XdpContext，eBPF 程序与单元测试共用
+
+pub const ETH_HDR_LEN: usize = 14;
+const VLAN_HDR_LEN: usize = 4;
+const IPV4_MIN_HDR_LEN: usize = 20;
+const TCP_MIN_HDR_LEN: usize = 20;
+const UDP_HDR_LEN: usize = 8;
+// SCTP 公共头部：源端口、目的端口、验证标签、校验和
+const SCTP_COMMON_HDR_LEN: usize = 12;
+
+const ETH_P_IP: u16 = 0x0800;
+const ETH_P_8021Q: u16 = 0x8100;
+const ETH_P_8021AD: u16 = 0x88a8;
+
+pub const IPPROTO_TCP: u8 = 6;
+pub const IPPROTO_UDP: u8 = 17;
+pub const IPPROTO_SCTP: u8 = 132;
+
+// 分片标志与片偏移：MF 位为 0x2000，低 13 位为片偏移
+const IP_MF: u16 = 0x2000;
+const IP_OFFSET_MASK: u16 = 0x1fff;
+
+// 按偏移读取大端整数，越界返回 None
+pub trait PacketReader {
+    fn read_u8(&self, offset: usize) -> Option<u8>;
+    fn read_u16(&self, offset: usize) -> Option<u16>;
+    fn read_u32(&self, offset: usize) -> Option<u32>;
+}
+
+impl PacketReader for [u8] {
+    fn read_u8(&self, offset: usize) -> Option<u8> {
+        self.get(offset).copied()
+    }
+
+    fn read_u16(&self, offset: usize) -> Option<u16> {
+        let bytes = self.get(offset..offset + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn read_u32(&self, offset: usize) -> Option<u32> {
+        let bytes = self.get(offset..offset + 4)?;
+        Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketInfo {
+    pub source_addr: u32,
+    pub dest_addr: u32,
+    pub protocol: u8,
+    // IPv4 头部中的总长度
+    pub ip_total_len: u16,
+    // 非首个分片不携带 L4 头部，端口记为 0
+    pub source_port: u16,
+    pub dest_port: u16,
+    pub fragmented: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    // 头部超出数据包末尾
+    Truncated,
+    // 不是 IPv4 数据包
+    NotIpv4,
+    // IPv4 头部长度或 L4 头部异常（UDP 长度 < 8、TCP 数据偏移越界、SCTP 头部超出 IP 总长度）
+    Malformed { fragmented: bool },
+}
+
+pub fn parse_packet<R: PacketReader + ?Sized>(packet: &R) -> Result<PacketInfo, ParseError> {
+    let mut ether_type = packet.read_u16(12).ok_or(ParseError::Truncated)?;
+    let mut ip_offset = ETH_HDR_LEN;
+    // 最多剥离一层 VLAN 标签
+    if ether_type == ETH_P_8021Q || ether_type == ETH_P_8021AD {
+        ether_type = packet
+            .read_u16(ETH_HDR_LEN + 2)
+            .ok_or(ParseError::Truncated)?;
+        ip_offset += VLAN_HDR_LEN;
+    }
+    if ether_type != ETH_P_IP {
+        return Err(ParseError::NotIpv4);
+    }
+
+    let version_ihl = packet.read_u8(ip_offset).ok_or(ParseError::Truncated)?;
+    let ip_total_len = packet
+        .read_u16(ip_offset + 2)
+        .ok_or(ParseError::Truncated)?;
+    let frag_off = packet
+        .read_u16(ip_offset + 6)
+        .ok_or(ParseError::Truncated)?;
+    let protocol = packet.read_u8(ip_offset + 9).ok_or(ParseError::Truncated)?;
+    let source_addr = packet
+        .read_u32(ip_offset + 12)
+        .ok_or(ParseError::Truncated)?;
+    let dest_addr = packet
+        .read_u32(ip_offset + 16)
+        .ok_or(ParseError::Truncated)?;
+
+    let fragmented = frag_off & (IP_MF | IP_OFFSET_MASK) != 0;
+    if version_ihl >> 4 != 4 {
+        return Err(ParseError::NotIpv4);
+    }
+    // IHL 以 4 字节为单位，至少 5（20 字节），包含 IP 选项
+    let ip_hdr_len = (version_ihl & 0x0f) as usize * 4;
+    if ip_hdr_len < IPV4_MIN_HDR_LEN || ip_hdr_len > ip_total_len as usize {
+        return Err(ParseError::Malformed { fragmented });
+    }
+
+    let mut info = PacketInfo {
+        source_addr,
+        dest_addr,
+        protocol,
+        ip_total_len,
+        source_port: 0,
+        dest_port: 0,
+        fragmented,
+    };
+    if frag_off & IP_OFFSET_MASK != 0 {
+        return Ok(info);
+    }
+
+    let l4_offset = ip_offset + ip_hdr_len;
+    // 按协议号选择 L4 解析，新增协议只需在 parse_ports 中加一个分支
+    let l4_len = ip_total_len as usize - ip_hdr_len;
+    (info.source_port, info.dest_port) =
+        parse_ports(packet, protocol, l4_offset, l4_len, fragmented)?;
+
+    Ok(info)
+}
+
+// 校验 L4 头部并读取端口，l4_len 为 IP 总长度减去 IP 头部长度
+fn parse_ports<R: PacketReader + ?Sized>(
+    packet: &R,
+    protocol: u8,
+    l4_offset: usize,
+    l4_len: usize,
+    fragmented: bool,
+) -> Result<(u16, u16), ParseError> {
+    let malformed = ParseError::Malformed { fragmented };
+    match protocol {
+        IPPROTO_TCP => {
+            // 数据偏移以 4 字节为单位，至少 5（20 字节），且头部不能超出 IP 总长度
+            let doff = (packet
+                .read_u8(l4_offset + 12)
+                .ok_or(ParseError::Truncated)?
+                >> 4) as usize;
+            if doff * 4 < TCP_MIN_HDR_LEN || doff * 4 > l4_len {
+                return Err(malformed);
+            }
+        }
+        IPPROTO_UDP => {
+            // UDP 长度包含 8 字节头部
+            let udp_len = packet
+                .read_u16(l4_offset + 4)
+                .ok_or(ParseError::Truncated)?;
+            if (udp_len as usize) < UDP_HDR_LEN {
+                return Err(malformed);
+            }
+        }
+        IPPROTO_SCTP => {
+            if l4_len < SCTP_COMMON_HDR_LEN {
+                return Err(malformed);
+            }
+        }
+        // 没有端口的协议（ICMP、GRE、ESP 等）照常统计，端口记为 0
+        _ => return Ok((0, 0)),
+    }
+
+    // TCP、UDP 与 SCTP 的源端口、目的端口都位于 L4 头部的前 4 个字节
+    let source_port = packet.read_u16(l4_offset).ok_or(ParseError::Truncated)?;
+    let dest_port = packet
+        .read_u16(l4_offset + 2)
+        .ok_or(ParseError::Truncated)?;
+    Ok((source_port, dest_port))
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use super::*;
+
+    const SRC: [u8; 4] = [10, 0, 0, 1];
+    const DST: [u8; 4] = [192, 168, 1, 2];
+
+    fn ethernet(ether_type: u16) -> Vec<u8> {
+        let mut packet = Vec::new();
+        packet.extend_from_slice(&[0xff; 6]);
+        packet.extend_from_slice(&[0x02, 0, 0, 0, 0, 1]);
+        packet.extend_from_slice(&ether_type.to_be_bytes());
+        packet
+    }
+
+    fn ipv4(protocol: u8, options: &[u8], frag_off: u16, payload_len: usize) -> Vec<u8> {
+        let hdr_len = IPV4_MIN_HDR_LEN + options.len();
+        let total_len = (hdr_len + payload_len) as u16;
+        let mut header = Vec::new();
+        header.push(0x40 | (hdr_len / 4) as u8);
+        header.push(0);
+        header.extend_from_slice(&total_len.to_be_bytes());
+        header.extend_from_slice(&[0, 0]);
+        header.extend_from_slice(&frag_off.to_be_bytes());
+        header.push(64);
+        header.push(protocol);
+        header.extend_from_slice(&[0, 0]);
+        header.extend_from_slice(&SRC);
+        header.extend_from_slice(&DST);
+        header.extend_from_slice(options);
+        header
+    }
+
+    fn tcp(source: u16, dest: u16) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&source.to_be_bytes());
+        header.extend_from_slice(&dest.to_be_bytes());
+        header.extend_from_slice(&[0; 8]);
+        header.push(5 << 4);
+        header.extend_from_slice(&[0x02, 0xff, 0xff, 0, 0, 0, 0]);
+        header
+    }
+
+    fn udp(source: u16, dest: u16, len: u16) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&source.to_be_bytes());
+        header.extend_from_slice(&dest.to_be_bytes());
+        header.extend_from_slice(&len.to_be_bytes());
+        header.extend_from_slice(&[0, 0]);
+        header
+    }
+
+    fn sctp(source: u16, dest: u16) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&source.to_be_bytes());
+        header.extend_from_slice(&dest.to_be_bytes());
+        header.extend_from_slice(&[0xde, 0xad, 0xbe, 0xef, 0, 0, 0, 0]);
+        header
+    }
+
+    fn build(l2: Vec<u8>, l3: Vec<u8>, l4: Vec<u8>) -> Vec<u8> {
+        let mut packet = l2;
+        packet.extend(l3);
+        packet.extend(l4);
+        packet
+    }
+
+    #[test]
+    fn parses_tcp() {
+        let packet = build(
+            ethernet(ETH_P_IP),
+            ipv4(IPPROTO_TCP, &[], 0, 20),
+            tcp(443, 51000),
+        );
+        let info = parse_packet(packet.as_slice()).unwrap();
+        assert_eq!(info.source_addr, u32::from_be_bytes(SRC));
+        assert_eq!(info.dest_addr, u32::from_be_bytes(DST));
+        assert_eq!(info.protocol, IPPROTO_TCP);
+        assert_eq!(info.ip_total_len, 40);
+        assert_eq!((info.source_port, info.dest_port), (443, 51000));
+        assert!(!info.fragmented);
+    }
+
+    #[test]
+    fn parses_udp() {
+        let packet = build(
+            ethernet(ETH_P_IP),
+            ipv4(IPPROTO_UDP, &[], 0, 12),
+            udp(53, 40000, 12),
+        );
+        let info = parse_packet(packet.as_slice()).unwrap();
+        assert_eq!(info.protocol, IPPROTO_UDP);
+        assert_eq!((info.source_port, info.dest_port), (53, 40000));
+    }
+
+    #[test]
+    fn parses_sctp() {
+        let packet = build(
+            ethernet(ETH_P_IP),
+            ipv4(IPPROTO_SCTP, &[], 0, 12),
+            sctp(3868, 38412),
+        );
+        let info = parse_packet(packet.as_slice()).unwrap();
+        assert_eq!(info.protocol, IPPROTO_SCTP);
+        assert_eq!((info.source_port, info.dest_port), (3868, 38412));
+
+        // IP 总长度放不下 SCTP 公共头部
+        let packet = build(
+            ethernet(ETH_P_IP),
+            ipv4(IPPROTO_SCTP, &[], 0, 8),
+            sctp(3868, 38412),
+        );
+        assert_eq!(
+            parse_packet(packet.as_slice()),
+            Err(ParseError::Malformed { fragmented: false })
+        );
+    }
+
+    #[test]
+    fn skips_ip_options() {
+        let packet = build(
+            ethernet(ETH_P_IP),
+            ipv4(IPPROTO_TCP, &[1, 1, 1, 0, 1, 1, 1, 0], 0, 20),
+            tcp(22, 60000),
+        );
+        let info = parse_packet(packet.as_slice()).unwrap();
+        assert_eq!(info.ip_total_len, 48);
+        assert_eq!((info.source_port, info.dest_port), (22, 60000));
+    }
+
+    #[test]
+    fn strips_vlan_tag() {
+        let mut l2 = ethernet(ETH_P_8021Q);
+        l2.extend_from_slice(&[0, 100]);
+        l2.extend_from_slice(&ETH_P_IP.to_be_bytes());
+        let packet = build(l2, ipv4(IPPROTO_UDP, &[], 0, 8), udp(123, 123, 8));
+        let info = parse_packet(packet.as_slice()).unwrap();
+        assert_eq!((info.source_port, info.dest_port), (123, 123));
+    }
+
+    #[test]
+    fn rejects_truncated_packets() {
+        let packet = build(
+            ethernet(ETH_P_IP),
+            ipv4(IPPROTO_TCP, &[], 0, 20),
+            tcp(80, 50000),
+        );
+        for len in [0, 10, ETH_HDR_LEN + 10, ETH_HDR_LEN + IPV4_MIN_HDR_LEN + 1] {
+            assert_eq!(
+                parse_packet(&packet[..len]),
+                Err(ParseError::Truncated),
+                "len {len}"
+            );
+        }
+    }
+
+    #[test]
+    fn ignores_non_ipv4() {
+        let packet = build(ethernet(0x86dd), Vec::from([0u8; 40]), Vec::new());
+        assert_eq!(parse_packet(packet.as_slice()), Err(ParseError::NotIpv4));
+    }
+
+    #[test]
+    fn non_first_fragment_has_no_ports() {
+        // 片偏移非 0，数据部分不是 L4 头部
+        let packet = build(
+            ethernet(ETH_P_IP),
+            ipv4(IPPROTO_UDP, &[], 185, 8),
+            Vec::from([0xaa; 8]),
+        );
+        let info = parse_packet(packet.as_slice()).unwrap();
+        assert!(info.fragmented);
+        assert_eq!((info.source_port, info.dest_port), (0, 0));
+    }
+
+    #[test]
+    fn first_fragment_keeps_ports() {
+        let packet = build(
+            ethernet(ETH_P_IP),
+            ipv4(IPPROTO_UDP, &[], IP_MF, 8),
+            udp(5000, 6000, 1400),
+        );
+        let info = parse_packet(packet.as_slice()).unwrap();
+        assert!(info.fragmented);
+        assert_eq!((info.source_port, info.dest_port), (5000, 6000));
+    }
+
+    #[test]
+    fn rejects_malformed_l4() {
+        let packet = build(
+            ethernet(ETH_P_IP),
+            ipv4(IPPROTO_UDP, &[], 0, 8),
+            udp(1, 2, 4),
+        );
+        assert_eq!(
+            parse_packet(packet.as_slice()),
+            Err(ParseError::Malformed { fragmented: false })
+        );
+
+        let mut bad_tcp = tcp(1, 2);
+        bad_tcp[12] = 4 << 4;
+        let packet = build(ethernet(ETH_P_IP), ipv4(IPPROTO_TCP, &[], 0, 20), bad_tcp);
+        assert_eq!(
+            parse_packet(packet.as_slice()),
+            Err(ParseError::Malformed { fragmented: false })
+        );
+    }
+
+    #[test]
+    fn parses_protocol_without_ports() {
+        let packet = build(ethernet(ETH_P_IP), ipv4(1, &[], 0, 8), Vec::from([0u8; 8]));
+        let info = parse_packet(packet.as_slice()).unwrap();
+        assert_eq!(info.protocol, 1);
+        assert_eq!((info.source_port, info.dest_port), (0, 0));
+    }
+}
@@ -3,36 +3,91 @@
 
 use aya_ebpf::{
     bindings::xdp_action,
+    helpers::{bpf_ktime_get_ns, gen::bpf_xdp_load_bytes},
     macros::{map, xdp},
-    maps::HashMap,
+    maps::{
+        lpm_trie::{Key, LpmTrie},
+        Array, HashMap, PerCpuArray, RingBuf,
+    },
     programs::XdpContext,
 };
 use aya_log_ebpf::info;
+use tc_common::{
+    peer_register, CaptureConfig, Diagnostics, PacketCapture, PeerRegisters, PortRange,
+    ProtocolStats, Settings, TrafficStats, CAPTURE_LEN, GAP_BUCKET_BOUNDS_NS, LENGTH_MODE_WIRE,
+    MAX_EXCLUDE_NETS, MAX_PORT_ONLY_IPS, MAX_PORT_RANGES, MAX_PROTOCOLS, MAX_STATS_ENTRIES,
+    MAX_TARGET_IPS, MAX_TARGET_PORTS, PEER_REGISTERS, PORT_FILTER_AND, PORT_FILTER_NONE,
+    PORT_FILTER_OR, RATE_IDLE_NS, STATS_PEERS, STATS_PROTOCOLS, STATS_TIMING,
+};
 
 use core::mem;
-use network_types::{
-    eth::{EthHdr, EtherType},
-    ip::{IpProto, Ipv4Hdr},
-    tcp::TcpHdr,
-    udp::UdpHdr,
-};
+use tc_common::packet::{parse_packet, PacketReader, ParseError};
+
+// 定义流量统计Map
+#[map]
+static TRAFFIC_STATS: HashMap<u32, TrafficStats> = HashMap::with_max_entries(MAX_STATS_ENTRIES, 0);
+
+#[map]
+static TARGET_IP: HashMap<u32, u8> = HashMap::with_max_entries(MAX_TARGET_IPS, 0);
+
+// 已为仅端口匹配的地址建立的 TRAFFIC_STATS 条目数，启动时由用户态按现有条目校正
+#[map]
+static PORT_ONLY_COUNT: Array<u64> = Array::with_max_entries(1, 0);
+
+#[map]
+static TARGET_PORT: HashMap<u16, u8> = HashMap::with_max_entries(MAX_TARGET_PORTS, 0);
+
+// 排除的地址与网段，键为网络字节序的 IPv4 前缀；命中时不统计，优先于 TARGET_IP 与端口匹配
+#[map]
+static EXCLUDE_IP: LpmTrie<u32, u8> = LpmTrie::with_max_entries(MAX_EXCLUDE_NETS, 0);
+
+// 端口范围，按顺序排列，遇到 end 为 0 的槽位即结束
+#[map]
+static PORT_RANGES: Array<PortRange> = Array::with_max_entries(MAX_PORT_RANGES as u32, 0);
 
-// 流量统计结构
+// 端口过滤策略 (PORT_FILTER_NONE / PORT_FILTER_AND / PORT_FILTER_OR)
+#[map]
+static PORT_FILTER: Array<u32> = Array::with_max_entries(1, 0);
+
+// 目标 IP -> 远端地址 HyperLogLog 寄存器
+#[map]
+static PEER_STATS: HashMap<u32, PeerRegisters> = HashMap::with_max_entries(MAX_STATS_ENTRIES, 0);
+
+// 入口接口 ifindex -> 方向 (DIRECTION_INBOUND / DIRECTION_OUTBOUND)
+#[map]
+static IFACE_DIRECTION: HashMap<u32, u32> = HashMap::with_max_entries(16, 0);
+
+// 抓包限速状态
 #[repr(C)]
 #[derive(Clone, Copy)]
-pub struct TrafficStats {
-    pub inbound_packets: u64,
-    pub inbound_bytes: u64,
-    pub outbound_packets: u64,
-    pub outbound_bytes: u64,
+pub struct CaptureRate {
+    pub window_start: u64,
+    pub count: u64,
 }
 
-// 定义流量统计Map
 #[map]
-static TRAFFIC_STATS: HashMap<u32, TrafficStats> = HashMap::with_max_entries(1024, 0);
+static SETTINGS: Array<Settings> = Array::with_max_entries(1, 0);
+
+#[map]
+static DIAGNOSTICS: PerCpuArray<Diagnostics> = PerCpuArray::with_max_entries(1, 0);
+
+// IP 协议号 -> 接口级别的协议统计
+#[map]
+static PROTOCOL_STATS: PerCpuArray<ProtocolStats> = PerCpuArray::with_max_entries(MAX_PROTOCOLS, 0);
+
+#[map]
+static CAPTURE_CONFIG: Array<CaptureConfig> = Array::with_max_entries(1, 0);
+
+#[map]
+static CAPTURE_RATE: Array<CaptureRate> = Array::with_max_entries(1, 0);
 
 #[map]
-static TARGET_IP: HashMap<u32, u8> = HashMap::with_max_entries(1024, 0);
+static CAPTURE_RING: RingBuf = RingBuf::with_byte_size(256 * 1024, 0);
+
+// EWMA 新样本权重为 1/8
+const RATE_EWMA_SHIFT: u64 = 3;
+// 包间隔下限，避免同一纳秒内的数据包产生极大样本
+const RATE_MIN_GAP_NS: u64 = 1_000;
 
 #[cfg(not(test))]
 #[panic_handler]
@@ -61,79 +116,350 @@ fn ptr_at<T>(ctx: &XdpContext, offset: usize) -> Result<*const T, ()> {
     Ok((start + offset) as *const T)
 }
 
-fn try_xdp_firewall(ctx: XdpContext) -> Result<u32, ()> {
-    let ethhdr: *const EthHdr = ptr_at(&ctx, 0)?; // (2)
-    match unsafe { (*ethhdr).ether_type } {
-        EtherType::Ipv4 => {}
-        _ => return Ok(xdp_action::XDP_PASS),
+// 让 tc_common::packet::parse_packet 直接读取 XDP 数据包
+struct XdpPacket<'a>(&'a XdpContext);
+
+impl PacketReader for XdpPacket<'_> {
+    #[inline(always)]
+    fn read_u8(&self, offset: usize) -> Option<u8> {
+        ptr_at::<u8>(self.0, offset).ok().map(|ptr| unsafe { *ptr })
     }
 
-    let ipv4hdr: *const Ipv4Hdr = ptr_at(&ctx, EthHdr::LEN)?;
-    let source_addr = u32::from_be_bytes(unsafe { (*ipv4hdr).src_addr });
-    let dest_addr = u32::from_be_bytes(unsafe { (*ipv4hdr).dst_addr });
+    #[inline(always)]
+    fn read_u16(&self, offset: usize) -> Option<u16> {
+        ptr_at::<[u8; 2]>(self.0, offset)
+            .ok()
+            .map(|ptr| u16::from_be_bytes(unsafe { *ptr }))
+    }
 
-    // 计算数据包大小
-    let packet_len = u16::from_be_bytes(unsafe { (*ipv4hdr).tot_len }) as u64;
+    #[inline(always)]
+    fn read_u32(&self, offset: usize) -> Option<u32> {
+        ptr_at::<[u8; 4]>(self.0, offset)
+            .ok()
+            .map(|ptr| u32::from_be_bytes(unsafe { *ptr }))
+    }
+}
 
-    let source_port = match unsafe { (*ipv4hdr).proto } {
-        IpProto::Tcp => {
-            let tcphdr: *const TcpHdr = ptr_at(&ctx, EthHdr::LEN + Ipv4Hdr::LEN)?;
-            u16::from_be(unsafe { (*tcphdr).source })
+// 记录与上一个数据包的间隔，首个数据包或时钟回退时不计入
+#[inline(always)]
+fn update_gap_histogram(stats: &mut TrafficStats, now: u64) {
+    if stats.last_seen_ns == 0 || now < stats.last_seen_ns {
+        return;
+    }
+    let gap = now - stats.last_seen_ns;
+    let bucket = if gap < GAP_BUCKET_BOUNDS_NS[0] {
+        0
+    } else if gap < GAP_BUCKET_BOUNDS_NS[1] {
+        1
+    } else if gap < GAP_BUCKET_BOUNDS_NS[2] {
+        2
+    } else {
+        3
+    };
+    stats.gap_buckets[bucket] += 1;
+}
+
+// 根据与上一个数据包的间隔更新字节速率 EWMA
+#[inline(always)]
+fn update_byte_rate(stats: &mut TrafficStats, packet_len: u64, now: u64) {
+    if stats.last_seen_ns != 0 {
+        let gap = now.saturating_sub(stats.last_seen_ns).max(RATE_MIN_GAP_NS);
+        let sample = packet_len * 1_000_000_000 / gap;
+        if gap >= RATE_IDLE_NS || stats.byte_rate_ewma == 0 {
+            // 第一个样本或长时间空闲后没有可用的旧速率，直接从新样本开始，避免从 0 缓慢爬升
+            stats.byte_rate_ewma = sample;
+        } else {
+            stats.byte_rate_ewma = stats.byte_rate_ewma - (stats.byte_rate_ewma >> RATE_EWMA_SHIFT)
+                + (sample >> RATE_EWMA_SHIFT);
         }
-        IpProto::Udp => {
-            let udphdr: *const UdpHdr = ptr_at(&ctx, EthHdr::LEN + Ipv4Hdr::LEN)?;
-            u16::from_be_bytes(unsafe { (*udphdr).source })
+    }
+    stats.last_seen_ns = now;
+}
+
+#[inline(always)]
+fn record_protocol(protocol: u8, packet_len: u64) {
+    if let Some(stats) = PROTOCOL_STATS.get_ptr_mut(protocol as u32) {
+        let stats = unsafe { &mut *stats };
+        stats.packets += 1;
+        stats.bytes += packet_len;
+    }
+}
+
+#[inline(always)]
+fn record_diagnostic(update: impl FnOnce(&mut Diagnostics)) {
+    if let Some(diagnostics) = DIAGNOSTICS.get_ptr_mut(0) {
+        update(unsafe { &mut *diagnostics });
+    }
+}
+
+// 每秒最多允许 max_per_sec 个数据包进入抓包环形缓冲区
+#[inline(always)]
+fn capture_allowed(max_per_sec: u32) -> bool {
+    let rate = match CAPTURE_RATE.get_ptr_mut(0) {
+        Some(rate) => rate,
+        None => return false,
+    };
+    let now = unsafe { bpf_ktime_get_ns() };
+
+    unsafe {
+        if now.wrapping_sub((*rate).window_start) >= 1_000_000_000 {
+            (*rate).window_start = now;
+            (*rate).count = 0;
+        }
+        if (*rate).count >= max_per_sec as u64 {
+            return false;
+        }
+        (*rate).count += 1;
+    }
+
+    true
+}
+
+// 将匹配抓包配置的数据包头部复制到环形缓冲区
+#[inline(always)]
+fn capture_packet(
+    ctx: &XdpContext,
+    source_addr: u32,
+    dest_addr: u32,
+    source_port: u16,
+    dest_port: u16,
+) {
+    let config = match CAPTURE_CONFIG.get(0) {
+        Some(config) if config.enabled != 0 => config,
+        _ => return,
+    };
+    if config.ipv4_address != source_addr && config.ipv4_address != dest_addr {
+        return;
+    }
+    if config.port != 0 && config.port != source_port && config.port != dest_port {
+        return;
+    }
+    if !capture_allowed(config.max_per_sec) {
+        return;
+    }
+
+    let mut entry = match CAPTURE_RING.reserve::<PacketCapture>(0) {
+        Some(entry) => entry,
+        None => return,
+    };
+
+    let frame_len = (ctx.data_end() - ctx.data()) as u32;
+    let captured_len = if frame_len >= CAPTURE_LEN as u32 {
+        CAPTURE_LEN as u32
+    } else {
+        // 掩码让校验器能确认长度上界
+        frame_len & (CAPTURE_LEN as u32 - 1)
+    };
+    if captured_len == 0 {
+        entry.discard(0);
+        return;
+    }
+
+    let capture = entry.as_mut_ptr();
+    let ret = unsafe {
+        (*capture).frame_len = frame_len;
+        (*capture).captured_len = captured_len;
+        bpf_xdp_load_bytes(
+            ctx.ctx,
+            0,
+            (*capture).data.as_mut_ptr() as *mut _,
+            captured_len,
+        )
+    };
+    if ret != 0 {
+        entry.discard(0);
+        return;
+    }
+
+    entry.submit(0);
+}
+
+#[inline(always)]
+fn port_in_ranges(port: u16) -> bool {
+    for index in 0..MAX_PORT_RANGES as u32 {
+        match PORT_RANGES.get(index) {
+            Some(range) if range.end != 0 => {
+                if port >= range.start && port <= range.end {
+                    return true;
+                }
+            }
+            _ => return false,
         }
-        _ => return Err(()),
+    }
+    false
+}
+
+// 源端口或目的端口命中 TARGET_PORT 中的端口或端口范围
+#[inline(always)]
+fn port_matches(source_port: u16, dest_port: u16) -> bool {
+    unsafe { TARGET_PORT.get(&source_port) }.is_some()
+        || unsafe { TARGET_PORT.get(&dest_port) }.is_some()
+        || port_in_ranges(source_port)
+        || port_in_ranges(dest_port)
+}
+
+// 按端口过滤策略判断该地址的流量是否需要统计
+#[inline(always)]
+fn should_record(addr: u32, port_matched: bool, port_filter: u32) -> bool {
+    if EXCLUDE_IP.get(&Key::new(32, addr.to_be())).is_some() {
+        return false;
+    }
+    let ip_matched = unsafe { TARGET_IP.get(&addr) }.is_some();
+    match port_filter {
+        PORT_FILTER_AND => ip_matched && port_matched,
+        PORT_FILTER_OR => ip_matched || port_matched,
+        _ => ip_matched,
+    }
+}
+
+#[inline(always)]
+fn reserve_port_only_slot() -> bool {
+    let Some(count) = PORT_ONLY_COUNT.get_ptr_mut(0) else {
+        return false;
     };
+    // BPF 目标不支持原子读改写，多个 CPU 同时遇到新地址时计数可能略少，超出部分由 insert_failed 体现
+    unsafe {
+        if *count >= MAX_PORT_ONLY_IPS as u64 {
+            return false;
+        }
+        *count += 1;
+    }
+    true
+}
 
-    // 统计入站流量 (源IP是目标IP)
-    if unsafe { TARGET_IP.get(&source_addr) }.is_some() {
-        info!(
-            &ctx,
-            "INBOUND - SRC IP: {:i}, SRC PORT: {}, SIZE: {} bytes",
-            source_addr,
-            source_port,
-            packet_len
-        );
+#[inline(always)]
+fn record_traffic(
+    ctx: &XdpContext,
+    addr: u32,
+    peer: u32,
+    addr_is_dest: bool,
+    iface_direction: Option<u32>,
+    packet_len: u64,
+    disabled_stats: u32,
+) {
+    let existing = unsafe { TRAFFIC_STATS.get(&addr) }.copied();
+    // 非目标地址首次出现时占用一个仅端口匹配的名额，名额用完不再新建条目
+    if existing.is_none() && unsafe { TARGET_IP.get(&addr) }.is_none() && !reserve_port_only_slot()
+    {
+        record_diagnostic(|diagnostics| diagnostics.port_only_dropped += 1);
+        return;
+    }
+    let mut stats = existing.unwrap_or(TrafficStats::new());
 
-        // 更新入站流量统计
-        let mut stats = unsafe { TRAFFIC_STATS.get(&source_addr) }
-            .copied()
-            .unwrap_or(TrafficStats {
-                inbound_packets: 0,
-                inbound_bytes: 0,
-                outbound_packets: 0,
-                outbound_bytes: 0,
-            });
+    if stats.record_packet(addr_is_dest, iface_direction, packet_len) {
+        info!(ctx, "INBOUND - IP: {:i}, SIZE: {} bytes", addr, packet_len);
+    } else {
+        info!(ctx, "OUTBOUND - IP: {:i}, SIZE: {} bytes", addr, packet_len);
+    }
+    if disabled_stats & STATS_TIMING == 0 {
+        // 时间戳只用于间隔与速率统计，关闭 timing 时不读取
+        let now = unsafe { bpf_ktime_get_ns() };
+        update_gap_histogram(&mut stats, now);
+        update_byte_rate(&mut stats, packet_len, now);
+    }
 
-        stats.inbound_packets += 1;
-        stats.inbound_bytes += packet_len;
+    if TRAFFIC_STATS.insert(&addr, &stats, 0).is_err() {
+        record_diagnostic(|diagnostics| diagnostics.insert_failed += 1);
+    }
 
-        let _ = TRAFFIC_STATS.insert(&source_addr, &stats, 0);
+    if disabled_stats & STATS_PEERS == 0 {
+        record_peer(addr, peer);
     }
+}
 
-    // 统计出站流量 (目标IP是目标IP)
-    if unsafe { TARGET_IP.get(&dest_addr) }.is_some() {
-        info!(
-            &ctx,
-            "OUTBOUND - DST IP: {:i}, SIZE: {} bytes", dest_addr, packet_len
-        );
+// 以 HyperLogLog 估算每个目标 IP 的不同远端地址数
+#[inline(always)]
+fn record_peer(addr: u32, peer: u32) {
+    let (index, rank) = peer_register(peer);
+    match PEER_STATS.get_ptr_mut(&addr) {
+        Some(peers) => {
+            if let Some(register) = unsafe { (*peers).registers.get_mut(index) } {
+                if *register < rank {
+                    *register = rank;
+                }
+            }
+        }
+        None => {
+            let mut peers = PeerRegisters {
+                registers: [0; PEER_REGISTERS],
+            };
+            if let Some(register) = peers.registers.get_mut(index) {
+                *register = rank;
+            }
+            if PEER_STATS.insert(&addr, &peers, 0).is_err() {
+                record_diagnostic(|diagnostics| diagnostics.insert_failed += 1);
+            }
+        }
+    }
+}
 
-        // 更新出站流量统计
-        let mut stats = unsafe { TRAFFIC_STATS.get(&dest_addr) }
-            .copied()
-            .unwrap_or(TrafficStats {
-                inbound_packets: 0,
-                inbound_bytes: 0,
-                outbound_packets: 0,
-                outbound_bytes: 0,
+fn try_xdp_firewall(ctx: XdpContext) -> Result<u32, ()> {
+    let info = match parse_packet(&XdpPacket(&ctx)) {
+        Ok(info) => info,
+        // 只观测不拦截：截断的帧同样放行
+        Err(ParseError::NotIpv4 | ParseError::Truncated) => return Ok(xdp_action::XDP_PASS),
+        Err(ParseError::Malformed { fragmented }) => {
+            record_diagnostic(|diagnostics| {
+                if fragmented {
+                    diagnostics.fragmented_packets += 1;
+                }
+                diagnostics.malformed_packets += 1;
             });
+            return Ok(xdp_action::XDP_PASS);
+        }
+    };
+    if info.fragmented {
+        record_diagnostic(|diagnostics| diagnostics.fragmented_packets += 1);
+    }
 
-        stats.outbound_packets += 1;
-        stats.outbound_bytes += packet_len;
+    let source_addr = info.source_addr;
+    let dest_addr = info.dest_addr;
+    let ip_total_len = info.ip_total_len as u64;
+    let source_port = info.source_port;
+    let dest_port = info.dest_port;
 
-        let _ = TRAFFIC_STATS.insert(&dest_addr, &stats, 0);
+    capture_packet(&ctx, source_addr, dest_addr, source_port, dest_port);
+
+    // 计算数据包大小
+    let settings = SETTINGS.get(0).copied().unwrap_or_default();
+    let packet_len = match settings.length_mode {
+        LENGTH_MODE_WIRE => (ctx.data_end() - ctx.data()) as u64,
+        _ => ip_total_len,
+    } + settings.length_overhead as u64;
+    if settings.disabled_stats & STATS_PROTOCOLS == 0 {
+        record_protocol(info.protocol, packet_len);
+    }
+
+    let port_filter = PORT_FILTER.get(0).copied().unwrap_or(PORT_FILTER_NONE);
+    let port_matched = port_filter != PORT_FILTER_NONE && port_matches(source_port, dest_port);
+
+    // 默认按地址位置判断方向（目的地址接收为入站，源地址发送为出站）；入口接口配置了方向时以接口为准
+    let iface_direction = unsafe { IFACE_DIRECTION.get(&(*ctx.ctx).ingress_ifindex) }.copied();
+
+    // 统计源 IP (默认计为出站)
+    if should_record(source_addr, port_matched, port_filter) {
+        record_traffic(
+            &ctx,
+            source_addr,
+            dest_addr,
+            false,
+            iface_direction,
+            packet_len,
+            settings.disabled_stats,
+        );
+    }
+
+    // 统计目的 IP (默认计为入站)
+    if should_record(dest_addr, port_matched, port_filter) {
+        record_traffic(
+            &ctx,
+            dest_addr,
+            source_addr,
+            true,
+            iface_direction,
+            packet_len,
+            settings.disabled_stats,
+        );
     }
 
     Ok(xdp_action::XDP_PASS)
@@ -0,0 +1,53 @@
+use std::fs;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::time::MissedTickBehavior;
+
+// 高频读取接口接收字节计数，记录两次展示之间的最大瞬时速率，捕捉 5 秒轮询会抹平的微突发
+pub struct BurstSampler {
+    peak_bps: Arc<AtomicU64>,
+}
+
+impl BurstSampler {
+    // 只读取 /sys/class/net/<iface>/statistics/rx_bytes 这一个计数器，不读取 eBPF Map
+    pub fn spawn(iface: &str, period: Duration) -> Self {
+        let peak_bps = Arc::new(AtomicU64::new(0));
+        let path = format!("/sys/class/net/{iface}/statistics/rx_bytes");
+        let peak = peak_bps.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+            let mut previous: Option<(Instant, u64)> = None;
+            loop {
+                interval.tick().await;
+                // 接口不存在等读取失败时重新开始计算，避免跨越中断的两个样本产生错误速率
+                let Some(rx_bytes) = read_counter(&path) else {
+                    previous = None;
+                    continue;
+                };
+                let now = Instant::now();
+                if let Some((last_time, last_bytes)) = previous.replace((now, rx_bytes)) {
+                    let elapsed = now.duration_since(last_time).as_secs_f64();
+                    if elapsed > 0.0 {
+                        let bps = (rx_bytes.saturating_sub(last_bytes) * 8) as f64 / elapsed;
+                        peak.fetch_max(bps as u64, Ordering::Relaxed);
+                    }
+                }
+            }
+        });
+
+        Self { peak_bps }
+    }
+
+    // 取出本展示周期内的峰值 (bit/s) 并清零
+    pub fn take_peak_bps(&self) -> u64 {
+        self.peak_bps.swap(0, Ordering::Relaxed)
+    }
+}
+
+fn read_counter(path: &str) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
@@ -0,0 +1,57 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::mem;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use aya::maps::{MapData, RingBuf};
+use tc_common::{PacketCapture, CAPTURE_LEN};
+use tokio::io::unix::AsyncFd;
+
+// pcap 链路类型：以太网
+const LINKTYPE_ETHERNET: u32 = 1;
+
+fn write_pcap_header(writer: &mut impl Write) -> std::io::Result<()> {
+    writer.write_all(&0xa1b2c3d4u32.to_le_bytes())?;
+    writer.write_all(&2u16.to_le_bytes())?;
+    writer.write_all(&4u16.to_le_bytes())?;
+    writer.write_all(&0i32.to_le_bytes())?;
+    writer.write_all(&0u32.to_le_bytes())?;
+    writer.write_all(&(CAPTURE_LEN as u32).to_le_bytes())?;
+    writer.write_all(&LINKTYPE_ETHERNET.to_le_bytes())
+}
+
+fn write_pcap_record(writer: &mut impl Write, capture: &PacketCapture) -> std::io::Result<()> {
+    // 内核时间戳是单调时钟，这里使用用户态读取时的墙钟时间
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let captured_len = (capture.captured_len as usize).min(CAPTURE_LEN);
+
+    writer.write_all(&(now.as_secs() as u32).to_le_bytes())?;
+    writer.write_all(&now.subsec_micros().to_le_bytes())?;
+    writer.write_all(&(captured_len as u32).to_le_bytes())?;
+    writer.write_all(&capture.frame_len.to_le_bytes())?;
+    writer.write_all(&capture.data[..captured_len])
+}
+
+// 从 CAPTURE_RING 读取数据包头部样本并写入 pcap 文件
+pub async fn run_capture(ring: RingBuf<MapData>, path: String) -> anyhow::Result<()> {
+    let mut writer = BufWriter::new(File::create(&path)?);
+    write_pcap_header(&mut writer)?;
+    writer.flush()?;
+
+    let mut fd = AsyncFd::new(ring)?;
+    loop {
+        let mut guard = fd.readable_mut().await?;
+        let ring = guard.get_inner_mut();
+        while let Some(item) = ring.next() {
+            if item.len() < mem::size_of::<PacketCapture>() {
+                continue;
+            }
+            let capture = unsafe { (item.as_ptr() as *const PacketCapture).read_unaligned() };
+            write_pcap_record(&mut writer, &capture)?;
+        }
+        writer.flush()?;
+        guard.clear_ready();
+    }
+}
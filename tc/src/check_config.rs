@@ -0,0 +1,134 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+use tc_common::{MAX_EXCLUDE_NETS, MAX_TARGET_IPS, MAX_TARGET_PORTS};
+
+use crate::env_file::parse_env_line;
+use crate::exclude_ip::{collapse_excludes, parse_exclude_ip};
+use crate::i18n::t;
+use crate::iface::resolve_interface;
+use crate::target_ip::parse_target_ip;
+use crate::target_label::parse_target_label;
+use crate::target_port::parse_target_port;
+use crate::targets::check_capacity;
+use crate::utils::u32_to_ip;
+
+// 检查 .env 配置文件与接口，不加载 eBPF 程序
+pub fn check_config(path: &Path, iface: &str) -> anyhow::Result<()> {
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    let mut target_ip_str = String::new();
+    let mut target_port_str = String::new();
+    let mut target_label_str = String::new();
+    let mut exclude_ip_str = String::new();
+
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("failed to read config file {}", path.display()))?;
+    for (line_no, line) in content.lines().enumerate() {
+        let (key, value) = match parse_env_line(line) {
+            Ok(Some(entry)) => entry,
+            Ok(None) => continue,
+            Err(e) => {
+                errors.push(t!(check_line_error, line_no + 1, e));
+                continue;
+            }
+        };
+        match key {
+            "TARGET_IP" => target_ip_str = value.to_string(),
+            "TARGET_PORT" => target_port_str = value.to_string(),
+            "TARGET_LABEL" => target_label_str = value.to_string(),
+            "EXCLUDE_IP" => exclude_ip_str = value.to_string(),
+            key => warnings.push(t!(check_unknown_key, key)),
+        }
+    }
+
+    let target_ip = match parse_target_ip(&target_ip_str) {
+        Ok(target_ips) => {
+            for ip in &target_ips.ipv6 {
+                warnings.push(t!(ipv6_target_skipped, ip));
+            }
+            let target_ip = target_ips.ipv4;
+            let distinct: HashSet<_> = target_ip.iter().collect();
+            if let Err(e) = check_capacity("TARGET_IP", distinct.len(), MAX_TARGET_IPS) {
+                errors.push(format!("{e:#}"));
+            }
+            if target_ip.is_empty() {
+                warnings.push(t!(check_target_ip_empty));
+            } else {
+                println!("{}", t!(check_target_ip, target_ip.len()));
+            }
+            target_ip
+        }
+        Err(e) => {
+            errors.push(format!("{e:#}"));
+            Vec::new()
+        }
+    };
+
+    match parse_target_port(&target_port_str) {
+        Ok(target_port) if target_port.is_empty() => println!("{}", t!(check_target_port_unset)),
+        Ok(target_port) => {
+            let distinct: HashSet<_> = target_port.ports.iter().collect();
+            if let Err(e) = check_capacity("TARGET_PORT", distinct.len(), MAX_TARGET_PORTS) {
+                errors.push(format!("{e:#}"));
+            }
+            println!(
+                "{}",
+                t!(
+                    check_target_port,
+                    target_port.ports.len(),
+                    target_port.ranges.len()
+                )
+            );
+        }
+        Err(e) => errors.push(format!("{e:#}")),
+    }
+
+    match parse_target_label(&target_label_str) {
+        Ok(target_label) if target_label.is_empty() => {}
+        Ok(target_label) => {
+            println!("{}", t!(check_target_label, target_label.len()));
+            for ip in target_label.keys() {
+                if !target_ip.iter().any(|target| target.0 == *ip) {
+                    warnings.push(t!(check_label_not_target, u32_to_ip(*ip)));
+                }
+            }
+        }
+        Err(e) => errors.push(format!("{e:#}")),
+    }
+
+    match parse_exclude_ip(&exclude_ip_str) {
+        Ok(exclude_ip) if exclude_ip.is_empty() => {}
+        Ok(exclude_ip) => {
+            let (exclude_ip, overlaps) = collapse_excludes(exclude_ip);
+            if let Err(e) = check_capacity("EXCLUDE_IP", exclude_ip.len(), MAX_EXCLUDE_NETS) {
+                errors.push(format!("{e:#}"));
+            }
+            println!("{}", t!(check_exclude_ip, exclude_ip.len()));
+            warnings.extend(overlaps.iter().map(|overlap| overlap.to_string()));
+        }
+        Err(e) => errors.push(format!("{e:#}")),
+    }
+
+    match resolve_interface(iface) {
+        Ok(resolved) if resolved != iface => println!("{}", t!(check_iface_auto, resolved)),
+        Ok(_) => println!("{}", t!(check_iface, iface)),
+        Err(e) => errors.push(format!("{e:#}")),
+    }
+
+    for warning in &warnings {
+        println!("{}", t!(check_warning, warning));
+    }
+    for error in &errors {
+        println!("{}", t!(check_error, error));
+    }
+
+    if !errors.is_empty() {
+        return Err(anyhow!(t!(check_failed, errors.len())));
+    }
+    println!("{}", t!(check_passed, warnings.len()));
+
+    Ok(())
+}
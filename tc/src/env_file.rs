@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+
+use crate::i18n::t;
+
+// 解析 .env 文件的一行，空行与注释返回 None
+pub fn parse_env_line(line: &str) -> anyhow::Result<Option<(&str, &str)>> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    let line = line.strip_prefix("export ").unwrap_or(line);
+    let Some((key, value)) = line.split_once('=') else {
+        return Err(anyhow!(t!(env_missing_equals)));
+    };
+    let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+    Ok(Some((key.trim(), value)))
+}
+
+// 读取 .env 文件中的全部配置项
+pub fn read_env_file(path: &Path) -> anyhow::Result<HashMap<String, String>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("failed to read config file {}", path.display()))?;
+    let mut values = HashMap::new();
+    for (line_no, line) in content.lines().enumerate() {
+        if let Some((key, value)) =
+            parse_env_line(line).with_context(|| t!(env_line, line_no + 1))?
+        {
+            values.insert(key.to_string(), value.to_string());
+        }
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_comments_and_blank_lines() {
+        assert_eq!(parse_env_line("").unwrap(), None);
+        assert_eq!(parse_env_line("   ").unwrap(), None);
+        assert_eq!(parse_env_line("# TARGET_IP=10.0.0.1").unwrap(), None);
+        assert_eq!(parse_env_line("  # comment").unwrap(), None);
+    }
+
+    #[test]
+    fn parses_plain_and_exported_lines() {
+        assert_eq!(
+            parse_env_line(" TARGET_IP = 10.0.0.1 ").unwrap(),
+            Some(("TARGET_IP", "10.0.0.1"))
+        );
+        assert_eq!(
+            parse_env_line("export TARGET_PORT=80,443").unwrap(),
+            Some(("TARGET_PORT", "80,443"))
+        );
+    }
+
+    #[test]
+    fn strips_quotes() {
+        assert_eq!(
+            parse_env_line("TARGET_LABEL=\"10.0.0.1=db\"").unwrap(),
+            Some(("TARGET_LABEL", "10.0.0.1=db"))
+        );
+        assert_eq!(
+            parse_env_line("EXCLUDE_IP='10.0.0.0/8'").unwrap(),
+            Some(("EXCLUDE_IP", "10.0.0.0/8"))
+        );
+    }
+
+    #[test]
+    fn rejects_line_without_equals() {
+        assert!(parse_env_line("TARGET_IP").is_err());
+    }
+
+    #[test]
+    fn keeps_present_but_empty_keys() {
+        assert_eq!(
+            parse_env_line("TARGET_IP=").unwrap(),
+            Some(("TARGET_IP", ""))
+        );
+        assert_eq!(
+            parse_env_line("TARGET_PORT=\"\"").unwrap(),
+            Some(("TARGET_PORT", ""))
+        );
+    }
+
+    #[test]
+    fn reads_file_with_empty_and_missing_keys() {
+        let path = std::env::temp_dir().join(format!("tc-env-{}.env", std::process::id()));
+        fs::write(
+            &path,
+            "# targets\nTARGET_IP=10.0.0.1\n\nexport TARGET_PORT=\n",
+        )
+        .unwrap();
+        let values = read_env_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            values.get("TARGET_IP").map(String::as_str),
+            Some("10.0.0.1")
+        );
+        // 存在但为空的键被读作空字符串，重新加载时据此清空配置；缺失的键不出现在结果中
+        assert_eq!(values.get("TARGET_PORT").map(String::as_str), Some(""));
+        assert!(!values.contains_key("EXCLUDE_IP"));
+        assert_eq!(values.len(), 2);
+    }
+
+    #[test]
+    fn reports_line_number_of_invalid_line() {
+        let path = std::env::temp_dir().join(format!("tc-env-bad-{}.env", std::process::id()));
+        fs::write(&path, "TARGET_IP=10.0.0.1\nTARGET_PORT\n").unwrap();
+        let err = read_env_file(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+
+        assert!(format!("{err:#}").contains('2'));
+    }
+}
@@ -0,0 +1,205 @@
+use std::env;
+use std::fmt;
+use std::net::Ipv4Addr;
+
+use anyhow::{anyhow, Context};
+use tc_common::utils::ip_to_u32;
+
+use crate::i18n::t;
+use crate::utils::u32_to_ip;
+
+// EXCLUDE_IP 中的一个地址或网段，单个地址视为 /32
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExcludeNet {
+    pub addr: u32,
+    pub prefix_len: u8,
+}
+
+impl ExcludeNet {
+    fn mask(&self) -> u32 {
+        u32::MAX
+            .checked_shl(32 - self.prefix_len as u32)
+            .unwrap_or(0)
+    }
+
+    // other 是否落在本网段内（含相同网段）
+    pub fn covers(&self, other: &ExcludeNet) -> bool {
+        self.prefix_len <= other.prefix_len && other.addr & self.mask() == self.addr
+    }
+}
+
+impl fmt::Display for ExcludeNet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", u32_to_ip(self.addr), self.prefix_len)
+    }
+}
+
+// 被更宽网段包含（或重复）而被合并掉的条目
+#[derive(Debug, Clone, Copy)]
+pub struct ExcludeOverlap {
+    pub covered: ExcludeNet,
+    pub by: ExcludeNet,
+}
+
+impl fmt::Display for ExcludeOverlap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.covered == self.by {
+            write!(f, "{}", t!(exclude_duplicate, self.covered))
+        } else {
+            write!(f, "{}", t!(exclude_covered, self.covered, self.by))
+        }
+    }
+}
+
+// EXCLUDE_IP=10.0.0.1,192.168.0.0/24，命中的地址不计入统计，优先于 TARGET_IP 与 TARGET_PORT
+pub fn parse_exclude_ip(exclude_ip_str: &str) -> anyhow::Result<Vec<ExcludeNet>> {
+    exclude_ip_str
+        .split(',')
+        .map(|entry| entry.trim())
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (ip, prefix_len) = match entry.split_once('/') {
+                Some((ip, prefix_len)) => (
+                    ip,
+                    prefix_len
+                        .trim()
+                        .parse::<u8>()
+                        .ok()
+                        .filter(|prefix_len| *prefix_len <= 32)
+                        .ok_or_else(|| {
+                            anyhow!("invalid EXCLUDE_IP entry: {entry}, prefix must be 0-32")
+                        })?,
+                ),
+                None => (entry, 32),
+            };
+            let ip = ip
+                .trim()
+                .parse::<Ipv4Addr>()
+                .with_context(|| format!("invalid EXCLUDE_IP entry: {entry}"))?;
+            let net = ExcludeNet {
+                addr: ip_to_u32(ip.octets()),
+                prefix_len,
+            };
+            // 主机位非零通常是写错了前缀，拒绝而不是静默截断
+            let network = ExcludeNet {
+                addr: net.addr & net.mask(),
+                ..net
+            };
+            if network != net {
+                return Err(anyhow!(
+                    "invalid EXCLUDE_IP entry: {entry}, host bits are set (network is {network})"
+                ));
+            }
+            Ok(net)
+        })
+        .collect()
+}
+
+// 去掉重复以及被更宽网段包含的条目，返回保留的条目与被合并的条目，顺序与输入一致
+pub fn collapse_excludes(nets: Vec<ExcludeNet>) -> (Vec<ExcludeNet>, Vec<ExcludeOverlap>) {
+    let mut kept: Vec<ExcludeNet> = Vec::new();
+    let mut overlaps = Vec::new();
+    for (index, net) in nets.iter().enumerate() {
+        // 前缀更短的网段优先；前缀相同（重复）时保留先出现的。
+        // 被多个网段包含时归到最宽的那个，它一定会被保留，结果与输入顺序无关
+        let by = nets
+            .iter()
+            .enumerate()
+            .filter(|(other_index, other)| {
+                *other_index != index
+                    && other.covers(net)
+                    && (other.prefix_len < net.prefix_len || *other_index < index)
+            })
+            .min_by_key(|(other_index, other)| (other.prefix_len, *other_index));
+        match by {
+            Some((_, by)) => overlaps.push(ExcludeOverlap {
+                covered: *net,
+                by: *by,
+            }),
+            None => kept.push(*net),
+        }
+    }
+    (kept, overlaps)
+}
+
+pub fn get_exclude_ip() -> anyhow::Result<Vec<ExcludeNet>> {
+    parse_exclude_ip(&env::var("EXCLUDE_IP").unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn net(addr: [u8; 4], prefix_len: u8) -> ExcludeNet {
+        ExcludeNet {
+            addr: ip_to_u32(addr),
+            prefix_len,
+        }
+    }
+
+    #[test]
+    fn parses_addresses_and_subnets() {
+        assert_eq!(
+            parse_exclude_ip(" 10.0.0.1 , 192.168.0.0/24,,").unwrap(),
+            vec![net([10, 0, 0, 1], 32), net([192, 168, 0, 0], 24)]
+        );
+    }
+
+    #[test]
+    fn accepts_prefix_bounds() {
+        assert_eq!(
+            parse_exclude_ip("0.0.0.0/0,10.0.0.1/32").unwrap(),
+            vec![net([0, 0, 0, 0], 0), net([10, 0, 0, 1], 32)]
+        );
+        assert!(parse_exclude_ip("10.0.0.0/33").is_err());
+    }
+
+    #[test]
+    fn rejects_host_bits() {
+        let err = parse_exclude_ip("10.1.2.3/8").unwrap_err().to_string();
+        assert!(err.contains("host bits are set"));
+        assert!(err.contains("10.0.0.0/8"));
+        assert!(parse_exclude_ip("1.0.0.0/0").is_err());
+    }
+
+    #[test]
+    fn collapses_duplicates_keeping_the_first() {
+        let a = net([10, 0, 0, 1], 32);
+        let (kept, overlaps) = collapse_excludes(vec![a, a]);
+        assert_eq!(kept, vec![a]);
+        assert_eq!(overlaps.len(), 1);
+        assert_eq!((overlaps[0].covered, overlaps[0].by), (a, a));
+    }
+
+    #[test]
+    fn collapses_nested_prefixes_in_any_order() {
+        let wide = net([10, 0, 0, 0], 8);
+        let narrow = net([10, 1, 0, 0], 16);
+        let host = net([10, 1, 2, 3], 32);
+        let other = net([192, 168, 0, 0], 24);
+
+        for nets in [
+            vec![wide, narrow, host, other],
+            vec![host, narrow, other, wide],
+        ] {
+            let (kept, overlaps) = collapse_excludes(nets.clone());
+            // 保留的条目顺序与输入一致
+            let expected: Vec<ExcludeNet> = nets
+                .iter()
+                .copied()
+                .filter(|net| *net == wide || *net == other)
+                .collect();
+            assert_eq!(kept, expected);
+            assert_eq!(overlaps.len(), 2);
+            assert!(overlaps.iter().all(|overlap| overlap.by == wide));
+        }
+    }
+
+    #[test]
+    fn zero_prefix_covers_everything() {
+        let all = net([0, 0, 0, 0], 0);
+        let (kept, overlaps) = collapse_excludes(vec![net([10, 0, 0, 1], 32), all]);
+        assert_eq!(kept, vec![all]);
+        assert_eq!(overlaps.len(), 1);
+    }
+}
@@ -0,0 +1,184 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use clap::ValueEnum;
+use tc_common::TrafficStats;
+
+/// 导出文件格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+impl ExportFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Csv => "csv",
+        }
+    }
+}
+
+// 导出文件名前缀，轮转时只清理带该前缀的文件
+const FILE_PREFIX: &str = "tc-";
+
+// 导出的一行：TRAFFIC_STATS 中的一个 IP
+pub struct ExportRecord {
+    pub ip: String,
+    pub label: Option<String>,
+    pub stats: TrafficStats,
+    pub byte_rate: u64,
+    pub distinct_peers: u64,
+    // 占本次快照全部流量的百分比
+    pub percent_of_total_bytes: f64,
+    pub percent_of_total_packets: f64,
+}
+
+pub struct Exporter {
+    dir: PathBuf,
+    format: ExportFormat,
+    keep: usize,
+}
+
+impl Exporter {
+    pub fn new(dir: &Path, format: ExportFormat, keep: usize) -> anyhow::Result<Self> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create export dir {}", dir.display()))?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            format,
+            keep,
+        })
+    }
+
+    // 写入一个带时间戳的快照文件并删除超出保留数量的旧文件
+    pub fn export(&self, records: &[ExportRecord]) -> anyhow::Result<PathBuf> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let extension = self.format.extension();
+        let path = self
+            .dir
+            .join(format!("{FILE_PREFIX}{timestamp}.{extension}"));
+
+        let content = match self.format {
+            ExportFormat::Json => to_json(timestamp, records),
+            ExportFormat::Csv => to_csv(records),
+        };
+
+        // 先写临时文件再改名，外部工具不会读到写了一半的文件
+        let tmp_path = path.with_extension(format!("{extension}.tmp"));
+        let mut file = fs::File::create(&tmp_path)
+            .with_context(|| format!("failed to create {}", tmp_path.display()))?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, &path)?;
+
+        self.rotate()?;
+
+        Ok(path)
+    }
+
+    fn rotate(&self) -> anyhow::Result<()> {
+        let suffix = format!(".{}", self.format.extension());
+        let mut files: Vec<(u64, PathBuf)> = fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                let timestamp = name.strip_prefix(FILE_PREFIX)?.strip_suffix(&suffix)?;
+                Some((timestamp.parse().ok()?, entry.path()))
+            })
+            .collect();
+        if files.len() <= self.keep {
+            return Ok(());
+        }
+
+        files.sort();
+        for (_, path) in &files[..files.len() - self.keep] {
+            fs::remove_file(path)
+                .with_context(|| format!("failed to remove {}", path.display()))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn to_json(timestamp: u64, records: &[ExportRecord]) -> String {
+    let entries: Vec<String> = records
+        .iter()
+        .map(|record| {
+            format!(
+                "    {{\"ip\": {}, \"label\": {}, \"inbound_packets\": {}, \"inbound_bytes\": {}, \"outbound_packets\": {}, \"outbound_bytes\": {}, \"byte_rate\": {}, \"distinct_peers\": {}, \"percent_of_total_bytes\": {:.2}, \"percent_of_total_packets\": {:.2}}}",
+                json_string(&record.ip),
+                record
+                    .label
+                    .as_deref()
+                    .map_or_else(|| "null".to_string(), json_string),
+                record.stats.inbound_packets,
+                record.stats.inbound_bytes,
+                record.stats.outbound_packets,
+                record.stats.outbound_bytes,
+                record.byte_rate,
+                record.distinct_peers,
+                record.percent_of_total_bytes,
+                record.percent_of_total_packets,
+            )
+        })
+        .collect();
+
+    format!(
+        "{{\n  \"timestamp\": {},\n  \"ips\": [\n{}\n  ]\n}}\n",
+        timestamp,
+        entries.join(",\n")
+    )
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn to_csv(records: &[ExportRecord]) -> String {
+    let mut csv = String::from(
+        "ip,label,inbound_packets,inbound_bytes,outbound_packets,outbound_bytes,byte_rate,distinct_peers,percent_of_total_bytes,percent_of_total_packets\n",
+    );
+    for record in records {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{:.2},{:.2}\n",
+            csv_field(&record.ip),
+            csv_field(record.label.as_deref().unwrap_or_default()),
+            record.stats.inbound_packets,
+            record.stats.inbound_bytes,
+            record.stats.outbound_packets,
+            record.stats.outbound_bytes,
+            record.byte_rate,
+            record.distinct_peers,
+            record.percent_of_total_bytes,
+            record.percent_of_total_packets,
+        ));
+    }
+    csv
+}
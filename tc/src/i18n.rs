@@ -0,0 +1,323 @@
+use std::fmt;
+use std::sync::OnceLock;
+
+use clap::ValueEnum;
+
+/// 控制台输出语言
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Lang {
+    /// 中文
+    Zh,
+    /// English
+    En,
+}
+
+// 控制台输出的文案，模板中的 {} 按顺序由 fill 替换
+pub struct Messages {
+    pub traffic_header: &'static str,
+    pub inbound_title: &'static str,
+    pub outbound_title: &'static str,
+    pub total_title: &'static str,
+    pub packets_line: &'static str,
+    pub bytes_line: &'static str,
+    pub rate_line: &'static str,
+    pub gap_line: &'static str,
+    pub peers_line: &'static str,
+    pub ratio_line: &'static str,
+    pub share_line: &'static str,
+    pub exfil_warning: &'static str,
+    pub protocol_line: &'static str,
+    pub protocol_entry: &'static str,
+    pub diagnostics_line: &'static str,
+    pub port_only_hidden: &'static str,
+    pub no_stats: &'static str,
+    pub hidden_ips: &'static str,
+    pub port_filter: &'static str,
+    pub port_filter_disabled: &'static str,
+    pub labels_updated: &'static str,
+    pub config_unchanged: &'static str,
+    pub iface_auto: &'static str,
+    pub iface_went_down: &'static str,
+    pub iface_still_down: &'static str,
+    pub iface_came_up: &'static str,
+    pub anonymize_enabled: &'static str,
+    pub iface_direction: &'static str,
+    pub inbound: &'static str,
+    pub outbound: &'static str,
+    pub capture_enabled: &'static str,
+    pub capture_error: &'static str,
+    pub attached: &'static str,
+    pub external_opened: &'static str,
+    pub external_ignored: &'static str,
+    pub reload_external: &'static str,
+    pub drift_missing: &'static str,
+    pub drift_unexpected: &'static str,
+    pub drift_rewritten: &'static str,
+    pub reconcile_failed: &'static str,
+    pub cycle_done: &'static str,
+    pub cycle_behind: &'static str,
+    pub loop_summary: &'static str,
+    pub burst_enabled: &'static str,
+    pub burst_line: &'static str,
+    pub monitoring: &'static str,
+    pub once_sampling: &'static str,
+    pub once_interrupted: &'static str,
+    pub running_hint: &'static str,
+    pub export_enabled: &'static str,
+    pub export_done: &'static str,
+    pub export_error: &'static str,
+    pub display_error: &'static str,
+    pub interface_stats_error: &'static str,
+    pub interface_delta: &'static str,
+    pub interface_mismatch: &'static str,
+    pub interface_stats_skipped: &'static str,
+    pub sighup_received: &'static str,
+    pub reload_failed: &'static str,
+    pub shutdown: &'static str,
+    pub state_restored: &'static str,
+    pub state_restore_failed: &'static str,
+    pub state_saved: &'static str,
+    pub state_save_failed: &'static str,
+    pub env_missing_equals: &'static str,
+    pub env_line: &'static str,
+    pub check_line_error: &'static str,
+    pub check_unknown_key: &'static str,
+    pub check_target_ip_empty: &'static str,
+    pub ipv6_target_skipped: &'static str,
+    pub check_target_ip: &'static str,
+    pub check_target_port_unset: &'static str,
+    pub check_target_port: &'static str,
+    pub check_target_label: &'static str,
+    pub check_exclude_ip: &'static str,
+    pub exclude_duplicate: &'static str,
+    pub exclude_covered: &'static str,
+    pub check_label_not_target: &'static str,
+    pub check_iface: &'static str,
+    pub check_iface_auto: &'static str,
+    pub check_warning: &'static str,
+    pub check_error: &'static str,
+    pub check_failed: &'static str,
+    pub check_passed: &'static str,
+}
+
+pub const ZH: Messages = Messages {
+    traffic_header: "\n=== 流量统计 for {} ===",
+    inbound_title: "入站流量:",
+    outbound_title: "出站流量:",
+    total_title: "总计:",
+    packets_line: "  数据包: {} 个",
+    bytes_line: "  字节数: {} bytes ({} KB)",
+    rate_line: "瞬时速率 (EWMA): {} KB/s",
+    gap_line: "包间隔分布: <1ms {}, <10ms {}, <100ms {}, >=100ms {} (>=10ms 占 {}%)",
+    peers_line: "远端 IP 数 (估算): {}",
+    ratio_line: "出站/入站比例: {}",
+    share_line: "占全部流量: 字节 {}%，数据包 {}%",
+    exfil_warning: "{} 出站/入站比例 {} 超过阈值 {}，疑似数据外泄",
+    protocol_line: "接口协议统计: {}",
+    protocol_entry: "{} {} 个 / {} bytes",
+    diagnostics_line: "诊断: 分片数据包 {} 个, 异常 L4 头部 {} 个, 仅端口匹配名额已满丢弃 {} 个, Map 插入失败 {} 次",
+    port_only_hidden: "另有 {} 个仅端口匹配的 IP 未展示（按字节数只展示前 {} 个）",
+    no_stats: "没有找到 {} 的流量统计数据",
+    hidden_ips: "{} 个 IP 流量低于展示阈值，已省略",
+    port_filter: "端口过滤 ({}): [{}]",
+    port_filter_disabled: "端口过滤已关闭",
+    labels_updated: "IP 标签已更新: {} 个",
+    config_unchanged: "配置没有变化",
+    iface_auto: "自动选择接口: {}",
+    iface_went_down: "接口 {} 已断开 (operstate {})，暂停输出统计",
+    iface_still_down: "接口 {} 仍处于断开状态 (operstate {})，跳过本周期统计",
+    iface_came_up: "接口 {} 已恢复，继续输出统计",
+    anonymize_enabled: "已开启 IP 匿名化，eBPF 逐包日志已关闭",
+    iface_direction: "接口 {} (ifindex {}) 的数据包计为{}",
+    inbound: "入站",
+    outbound: "出站",
+    capture_enabled: "抓包已开启: {}:{} -> {}",
+    capture_error: "抓包任务出错: {}",
+    attached: "XDP程序已加载并附加到 {} 接口",
+    external_opened: "外部加载模式: 从 {} 读取固定的 Map",
+    external_ignored: "外部加载模式下以下参数不生效: {}",
+    reload_external: "外部加载模式下目标由加载程序管理，忽略重新加载",
+    drift_missing: "{} 中缺少配置的条目 {}，已重新插入",
+    drift_unexpected: "{} 中存在未配置的条目 {}，已删除",
+    drift_rewritten: "{} 与配置不一致，已重写",
+    reconcile_failed: "校对目标 Map 失败: {}",
+    cycle_done: "{} 周期耗时 {} ms，读取 Map {} 次",
+    cycle_behind:
+        "{} 周期耗时 {} ms，超过 {} 秒的间隔，统计已落后，可考虑 --lite 或 --disable-stats",
+    loop_summary: "主循环共 {} 个周期，其中 {} 个超时，最长耗时 {} ms",
+    burst_enabled: "已开启突发采样：每 {} ms 读取 {} 的接收字节数",
+    burst_line: "{} 本周期突发峰值: {} Mbit/s",
+    monitoring: "开始监控 [{}] 的流量...",
+    once_sampling: "采样 {} 秒后输出统计并退出",
+    once_interrupted: "收到 Ctrl-C 信号，提前结束采样",
+    running_hint: "按 Ctrl-C 退出，发送 SIGHUP 重新加载 TARGET_IP / TARGET_PORT / TARGET_LABEL / EXCLUDE_IP",
+    export_enabled: "每 {} 秒导出快照到 {} ({}, 保留 {} 个)",
+    export_done: "快照已导出: {}",
+    export_error: "导出快照时出错: {}",
+    display_error: "显示统计信息时出错: {}",
+    interface_stats_error: "读取接口统计时出错: {}",
+    interface_delta: "接口 {} 本周期: 接收 {} bytes, 发送 {} bytes; tc 解析 IPv4 {} bytes",
+    interface_mismatch:
+        "tc 解析的 IPv4 字节数 ({}) 大于接口 {} 接收字节数 ({})，请检查 XDP 程序是否还附加在其他接口上",
+    interface_stats_skipped: "使用 {} 时 tc 的字节数无法与单个接口的计数对比，已跳过 --interface-stats",
+    sighup_received: "收到 SIGHUP 信号，重新加载配置",
+    reload_failed: "重新加载配置失败，继续使用当前配置: {}",
+    shutdown: "收到 Ctrl-C 信号，正在退出...",
+    state_restored: "已恢复 {} 个 IP 的统计: {}",
+    state_restore_failed: "恢复统计状态失败，从空统计开始: {}",
+    state_saved: "统计状态已保存: {}",
+    state_save_failed: "保存统计状态失败: {}",
+    env_missing_equals: "缺少 '='",
+    env_line: "第 {} 行",
+    check_line_error: "第 {} 行{}",
+    check_unknown_key: "未知配置项 {}",
+    check_target_ip_empty: "TARGET_IP 为空，不会监控任何 IP",
+    ipv6_target_skipped: "TARGET_IP 中的 {} 是 IPv6 地址，XDP 程序目前只解析 IPv4，已忽略",
+    check_target_ip: "TARGET_IP: {} 个",
+    check_target_port_unset: "TARGET_PORT: 未配置",
+    check_target_port: "TARGET_PORT: {} 个端口, {} 个范围",
+    check_target_label: "TARGET_LABEL: {} 个",
+    check_exclude_ip: "EXCLUDE_IP: {} 个",
+    exclude_duplicate: "EXCLUDE_IP 条目 {} 重复，已忽略",
+    exclude_covered: "EXCLUDE_IP 条目 {} 已被 {} 包含，已合并",
+    check_label_not_target: "TARGET_LABEL 中的 {} 不在 TARGET_IP 中",
+    check_iface: "接口: {}",
+    check_iface_auto: "接口: {} (自动选择)",
+    check_warning: "警告: {}",
+    check_error: "错误: {}",
+    check_failed: "配置检查发现 {} 个错误",
+    check_passed: "配置检查通过 ({} 个警告)",
+};
+
+pub const EN: Messages = Messages {
+    traffic_header: "\n=== Traffic stats for {} ===",
+    inbound_title: "Inbound:",
+    outbound_title: "Outbound:",
+    total_title: "Total:",
+    packets_line: "  Packets: {}",
+    bytes_line: "  Bytes: {} bytes ({} KB)",
+    rate_line: "Instant rate (EWMA): {} KB/s",
+    gap_line: "Inter-packet gaps: <1ms {}, <10ms {}, <100ms {}, >=100ms {} ({}% >=10ms)",
+    peers_line: "Distinct remote IPs (estimated): {}",
+    ratio_line: "Outbound/inbound ratio: {}",
+    share_line: "Share of all traffic: {}% of bytes, {}% of packets",
+    exfil_warning: "{} outbound/inbound ratio {} exceeds the threshold {}, possible data exfiltration",
+    protocol_line: "Interface protocols: {}",
+    protocol_entry: "{} {} packets / {} bytes",
+    diagnostics_line: "Diagnostics: {} fragmented packets, {} malformed L4 headers, {} dropped with the port-only cap reached, {} failed map inserts",
+    port_only_hidden: "{} more port-only IPs not shown (only the top {} by bytes are listed)",
+    no_stats: "No traffic stats found for {}",
+    hidden_ips: "{} IPs below the display threshold were omitted",
+    port_filter: "Port filter ({}): [{}]",
+    port_filter_disabled: "Port filter disabled",
+    labels_updated: "IP labels updated: {}",
+    config_unchanged: "Configuration unchanged",
+    iface_auto: "Auto-selected interface: {}",
+    iface_went_down: "Interface {} is down (operstate {}), pausing stats output",
+    iface_still_down: "Interface {} is still down (operstate {}), skipping this interval",
+    iface_came_up: "Interface {} is back up, resuming stats output",
+    anonymize_enabled: "IP anonymization enabled, per-packet eBPF log disabled",
+    iface_direction: "Packets received on {} (ifindex {}) are counted as {}",
+    inbound: "inbound",
+    outbound: "outbound",
+    capture_enabled: "Capture enabled: {}:{} -> {}",
+    capture_error: "Capture task failed: {}",
+    attached: "XDP program loaded and attached to {}",
+    external_opened: "External attach mode: reading pinned maps from {}",
+    external_ignored: "These options have no effect in external attach mode: {}",
+    reload_external: "Targets are managed by the external loader in external attach mode, ignoring reload",
+    drift_missing: "{} is missing configured entry {}, re-inserted",
+    drift_unexpected: "{} contains unconfigured entry {}, removed",
+    drift_rewritten: "{} did not match the configuration, rewritten",
+    reconcile_failed: "Failed to reconcile target maps: {}",
+    cycle_done: "{} cycle took {} ms, {} map reads",
+    cycle_behind: "{} cycle took {} ms, longer than its {}s interval; tc is behind schedule, consider --lite or --disable-stats",
+    loop_summary: "Main loop ran {} cycles, {} behind schedule, slowest {} ms",
+    burst_enabled: "Burst sampling enabled: every {} ms, reading RX bytes of {}",
+    burst_line: "{} peak burst this interval: {} Mbit/s",
+    monitoring: "Monitoring traffic for [{}]...",
+    once_sampling: "Sampling for {} seconds, then printing stats and exiting",
+    once_interrupted: "Received Ctrl-C, ending the sample early",
+    running_hint: "Press Ctrl-C to exit, send SIGHUP to reload TARGET_IP / TARGET_PORT / TARGET_LABEL / EXCLUDE_IP",
+    export_enabled: "Exporting a snapshot every {} seconds to {} ({}, keeping {})",
+    export_done: "Snapshot exported: {}",
+    export_error: "Failed to export snapshot: {}",
+    display_error: "Failed to display stats: {}",
+    interface_stats_error: "Failed to read interface stats: {}",
+    interface_delta: "Interface {} this interval: RX {} bytes, TX {} bytes; tc parsed {} bytes of IPv4",
+    interface_mismatch: "tc parsed {} bytes of IPv4 traffic, more than interface {} received ({}); check whether the XDP program is attached to other interfaces as well",
+    interface_stats_skipped: "tc's byte count cannot be compared with a single interface's counters under {}; --interface-stats is skipped",
+    sighup_received: "Received SIGHUP, reloading configuration",
+    reload_failed: "Reload failed, keeping the current configuration: {}",
+    shutdown: "Received Ctrl-C, exiting...",
+    state_restored: "Restored stats for {} IPs from {}",
+    state_restore_failed: "Failed to restore saved state, starting from empty stats: {}",
+    state_saved: "Stats state saved to {}",
+    state_save_failed: "Failed to save stats state: {}",
+    env_missing_equals: "missing '='",
+    env_line: "line {}",
+    check_line_error: "line {}: {}",
+    check_unknown_key: "unknown key {}",
+    check_target_ip_empty: "TARGET_IP is empty, no IP will be monitored",
+    ipv6_target_skipped: "{} in TARGET_IP is an IPv6 address; the XDP program only parses IPv4 for now, ignoring it",
+    check_target_ip: "TARGET_IP: {} entries",
+    check_target_port_unset: "TARGET_PORT: not set",
+    check_target_port: "TARGET_PORT: {} ports, {} ranges",
+    check_target_label: "TARGET_LABEL: {} entries",
+    check_exclude_ip: "EXCLUDE_IP: {} entries",
+    exclude_duplicate: "EXCLUDE_IP entry {} is a duplicate, ignored",
+    exclude_covered: "EXCLUDE_IP entry {} is covered by {}, collapsed",
+    check_label_not_target: "{} in TARGET_LABEL is not in TARGET_IP",
+    check_iface: "Interface: {}",
+    check_iface_auto: "Interface: {} (auto-selected)",
+    check_warning: "warning: {}",
+    check_error: "error: {}",
+    check_failed: "config check found {} errors",
+    check_passed: "config check passed ({} warnings)",
+};
+
+static MESSAGES: OnceLock<&'static Messages> = OnceLock::new();
+
+pub fn set_lang(lang: Lang) {
+    let _ = MESSAGES.set(match lang {
+        Lang::Zh => &ZH,
+        Lang::En => &EN,
+    });
+}
+
+// 未调用 set_lang 时默认中文
+pub fn messages() -> &'static Messages {
+    MESSAGES.get().copied().unwrap_or(&ZH)
+}
+
+// 依次用参数替换模板中的 {}
+pub fn fill(template: &str, args: &[&dyn fmt::Display]) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut args = args.iter();
+    let mut parts = template.split("{}");
+    if let Some(first) = parts.next() {
+        output.push_str(first);
+    }
+    for part in parts {
+        if let Some(arg) = args.next() {
+            output.push_str(&arg.to_string());
+        }
+        output.push_str(part);
+    }
+    output
+}
+
+// t!(key) 或 t!(key, arg...)：取当前语言的文案并填充参数
+macro_rules! t {
+    ($key:ident) => {
+        $crate::i18n::messages().$key.to_string()
+    };
+    ($key:ident, $($arg:expr),+ $(,)?) => {
+        $crate::i18n::fill(
+            $crate::i18n::messages().$key,
+            &[$(&$arg as &dyn std::fmt::Display),+],
+        )
+    };
+}
+
+pub(crate) use t;
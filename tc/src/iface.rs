@@ -0,0 +1,64 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+
+// --iface 取该值时自动选择默认路由所在的接口
+pub const AUTO_IFACE: &str = "auto";
+
+const RTF_UP: u32 = 0x1;
+
+pub fn validate_interface(iface: &str) -> anyhow::Result<()> {
+    if iface.is_empty() {
+        return Err(anyhow!("interface name is empty"));
+    }
+    if !Path::new("/sys/class/net").join(iface).exists() {
+        return Err(anyhow!("interface {iface} does not exist"));
+    }
+    Ok(())
+}
+
+// 接口不可用时返回其 operstate（down、lowerlayerdown、notpresent），接口被删除时视为 notpresent
+// 回环、tun 等接口的 operstate 通常为 unknown，视为可用
+pub fn interface_down(iface: &str) -> Option<String> {
+    let state = fs::read_to_string(Path::new("/sys/class/net").join(iface).join("operstate"))
+        .map(|state| state.trim().to_string())
+        .unwrap_or_else(|_| "notpresent".to_string());
+    matches!(state.as_str(), "down" | "lowerlayerdown" | "notpresent").then_some(state)
+}
+
+// 从 /proc/net/route 中选择 metric 最小的非回环 IPv4 默认路由接口
+pub fn default_route_interface() -> anyhow::Result<String> {
+    let content =
+        fs::read_to_string("/proc/net/route").context("failed to read /proc/net/route")?;
+
+    content
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 8 || fields[0] == "lo" {
+                return None;
+            }
+            let flags = u32::from_str_radix(fields[3], 16).ok()?;
+            if fields[1] != "00000000" || fields[7] != "00000000" || flags & RTF_UP == 0 {
+                return None;
+            }
+            let metric: u32 = fields[6].parse().ok()?;
+            Some((metric, fields[0].to_string()))
+        })
+        .min()
+        .map(|(_, iface)| iface)
+        .ok_or_else(|| anyhow!("no default route found in /proc/net/route"))
+}
+
+// 解析 --iface，处理 auto 并确认接口存在
+pub fn resolve_interface(iface: &str) -> anyhow::Result<String> {
+    let iface = if iface == AUTO_IFACE {
+        default_route_interface()?
+    } else {
+        iface.to_string()
+    };
+    validate_interface(&iface)?;
+    Ok(iface)
+}
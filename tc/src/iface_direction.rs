@@ -0,0 +1,41 @@
+use std::ffi::CString;
+
+use anyhow::anyhow;
+use tc_common::{DIRECTION_INBOUND, DIRECTION_OUTBOUND};
+
+// --direction-iface eth0=inbound：该接口收到的数据包固定计为入站
+#[derive(Debug, Clone)]
+pub struct IfaceDirection {
+    pub iface: String,
+    pub direction: u32,
+}
+
+pub fn parse_iface_direction(value: &str) -> Result<IfaceDirection, String> {
+    let (iface, direction) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected <iface>=<inbound|outbound>, got {value}"))?;
+    if iface.is_empty() {
+        return Err("interface name is empty".to_string());
+    }
+    let direction = match direction {
+        "inbound" => DIRECTION_INBOUND,
+        "outbound" => DIRECTION_OUTBOUND,
+        _ => {
+            return Err(format!(
+                "unknown direction {direction}, expected inbound or outbound"
+            ))
+        }
+    };
+    Ok(IfaceDirection {
+        iface: iface.to_string(),
+        direction,
+    })
+}
+
+pub fn ifindex(iface: &str) -> anyhow::Result<u32> {
+    let name = CString::new(iface)?;
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => Err(anyhow!("interface {iface} does not exist")),
+        index => Ok(index),
+    }
+}
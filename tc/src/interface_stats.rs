@@ -0,0 +1,91 @@
+use std::fs;
+
+use anyhow::{anyhow, Context};
+use log::{info, warn};
+
+use crate::i18n::t;
+
+// /proc/net/dev 中的接口收发字节数
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InterfaceCounters {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
+
+pub fn read_interface_counters(iface: &str) -> anyhow::Result<InterfaceCounters> {
+    let content = fs::read_to_string("/proc/net/dev").context("failed to read /proc/net/dev")?;
+
+    for line in content.lines().skip(2) {
+        let Some((name, fields)) = line.split_once(':') else {
+            continue;
+        };
+        if name.trim() != iface {
+            continue;
+        }
+
+        let fields: Vec<u64> = fields
+            .split_whitespace()
+            .map(|field| field.parse().unwrap_or(0))
+            .collect();
+        if fields.len() < 9 {
+            return Err(anyhow!("unexpected /proc/net/dev format for {iface}"));
+        }
+
+        return Ok(InterfaceCounters {
+            rx_bytes: fields[0],
+            tx_bytes: fields[8],
+        });
+    }
+
+    Err(anyhow!("interface {iface} not found in /proc/net/dev"))
+}
+
+// 每个周期对比内核接口计数与 tc 统计的增量
+pub struct InterfaceCrossCheck {
+    iface: String,
+    previous_interface: Option<InterfaceCounters>,
+    previous_monitored_bytes: u64,
+}
+
+impl InterfaceCrossCheck {
+    pub fn new(iface: &str) -> Self {
+        Self {
+            iface: iface.to_string(),
+            previous_interface: None,
+            previous_monitored_bytes: 0,
+        }
+    }
+
+    pub fn check(&mut self, monitored_bytes: u64) -> anyhow::Result<()> {
+        let current = read_interface_counters(&self.iface)?;
+        let monitored_delta = monitored_bytes.saturating_sub(self.previous_monitored_bytes);
+        self.previous_monitored_bytes = monitored_bytes;
+
+        let Some(previous) = self.previous_interface.replace(current) else {
+            return Ok(());
+        };
+        let rx_delta = current.rx_bytes.saturating_sub(previous.rx_bytes);
+        let tx_delta = current.tx_bytes.saturating_sub(previous.tx_bytes);
+
+        info!(
+            "{}",
+            t!(
+                interface_delta,
+                self.iface,
+                rx_delta,
+                tx_delta,
+                monitored_delta
+            )
+        );
+
+        // XDP 只能看到接收方向的数据包，因此 tc 的统计不应超过接口接收字节数
+        if monitored_delta > rx_delta {
+            warn!(
+                "{}",
+                t!(interface_mismatch, monitored_delta, self.iface, rx_delta)
+            );
+        }
+
+        Ok(())
+    }
+}
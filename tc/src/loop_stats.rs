@@ -0,0 +1,42 @@
+use std::time::{Duration, Instant};
+
+use log::{debug, info, warn};
+
+use crate::i18n::t;
+
+// 主循环各周期的耗时与读取的条目数，用于判断 tc 是否跟得上设定的间隔
+#[derive(Debug, Default)]
+pub struct LoopStats {
+    cycles: u64,
+    behind: u64,
+    slowest: Duration,
+}
+
+impl LoopStats {
+    // 记录一个周期，name 为周期名称，entries 为本周期读取的 Map 条目数
+    pub fn record(&mut self, name: &str, started: Instant, entries: usize, interval: Duration) {
+        let elapsed = started.elapsed();
+        self.cycles += 1;
+        self.slowest = self.slowest.max(elapsed);
+
+        debug!("{}", t!(cycle_done, name, millis(elapsed), entries));
+        if elapsed > interval {
+            self.behind += 1;
+            warn!(
+                "{}",
+                t!(cycle_behind, name, millis(elapsed), interval.as_secs())
+            );
+        }
+    }
+
+    pub fn log_summary(&self) {
+        info!(
+            "{}",
+            t!(loop_summary, self.cycles, self.behind, millis(self.slowest))
+        );
+    }
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.1}", duration.as_secs_f64() * 1000.0)
+}
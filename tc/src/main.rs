@@ -1,142 +1,1193 @@
 use anyhow::Context;
-use aya::maps::HashMap;
+use aya::maps::{Array, HashMap, MapData, PerCpuArray, RingBuf};
 use aya::programs::{Xdp, XdpFlags};
 use aya_log::EbpfLogger;
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 use log::{debug, info, warn, LevelFilter};
 use std::env;
-use std::time::Duration;
-use tc_common::TrafficStats;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tc_common::{
+    CaptureConfig, Diagnostics, PeerRegisters, ProtocolStats, Settings, TrafficStats,
+    DIRECTION_INBOUND, ETHERNET_FCS_LEN, ETHERNET_HEADER_LEN, ETHERNET_PREAMBLE_IFG_LEN,
+    LENGTH_MODE_IP, LENGTH_MODE_WIRE, MAX_PROTOCOLS, PORT_FILTER_AND, PORT_FILTER_NONE,
+    PORT_FILTER_OR, STATS_PEERS, STATS_PROTOCOLS, STATS_TIMING,
+};
 use tokio::signal;
+use tokio::signal::unix::SignalKind;
 
-use crate::target_ip::{get_target_ip, TargetIp};
+use crate::burst::BurstSampler;
+use crate::env_file::read_env_file;
+use crate::exclude_ip::{
+    collapse_excludes, get_exclude_ip, parse_exclude_ip, ExcludeNet, ExcludeOverlap,
+};
+use crate::export::{ExportFormat, ExportRecord, Exporter};
+use crate::i18n::{t, Lang};
+use crate::iface::{interface_down, resolve_interface, AUTO_IFACE};
+use crate::iface_direction::{ifindex, parse_iface_direction, IfaceDirection};
+use crate::interface_stats::InterfaceCrossCheck;
+use crate::loop_stats::LoopStats;
+use crate::maps::StatsMaps;
+use crate::peers::estimate_distinct_peers;
+use crate::state::MapState;
+use crate::target_ip::{get_target_ip, parse_target_ip, TargetIp, TargetIps};
+use crate::target_label::{get_target_label, parse_target_label};
+use crate::target_port::{get_target_port, parse_target_port, TargetPorts};
+use crate::targets::{TargetChanges, TargetDrift, Targets};
+use crate::utils::{
+    anonymize_ip, anonymize_ipv6, monotonic_ns, percent_of, protocol_name, u32_to_ip,
+};
 
+mod burst;
+mod capture;
+mod check_config;
+mod env_file;
+mod exclude_ip;
+mod export;
+mod i18n;
+mod iface;
+mod iface_direction;
+mod interface_stats;
+mod loop_stats;
+mod maps;
+mod peers;
+mod state;
 mod target_ip;
+mod target_label;
+mod target_port;
+mod targets;
 mod utils;
 
+// 控制台展示周期
+const DISPLAY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// TARGET_PORT 与 TARGET_IP 的组合方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum PortPolicy {
+    /// IP 与端口同时匹配才统计
+    And,
+    /// IP 或端口任一匹配即统计
+    Or,
+}
+
+/// 字节统计口径
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum PacketLength {
+    /// IPv4 头部中的总长度，不含以太网头部
+    Ip,
+    /// XDP 看到的完整帧长度，含以太网头部
+    Wire,
+    /// IPv4 总长度 + 以太网头部 (14) + FCS (4)，与交换机/网卡的字节计数口径一致
+    Ethernet,
+    /// ethernet 再加前导码 (8) 与帧间隔 (12)，即占用的线路字节数
+    Line,
+}
+
+impl PacketLength {
+    // eBPF 中的长度来源与每包固定开销
+    fn settings(self) -> (u32, u32) {
+        let ethernet = ETHERNET_HEADER_LEN + ETHERNET_FCS_LEN;
+        match self {
+            PacketLength::Ip => (LENGTH_MODE_IP, 0),
+            PacketLength::Wire => (LENGTH_MODE_WIRE, 0),
+            PacketLength::Ethernet => (LENGTH_MODE_IP, ethernet),
+            PacketLength::Line => (LENGTH_MODE_IP, ethernet + ETHERNET_PREAMBLE_IFG_LEN),
+        }
+    }
+}
+
+/// 可关闭的统计项
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum StatsDetail {
+    /// PEER_STATS 远端 IP 数估算
+    Peers,
+    /// PROTOCOL_STATS 接口协议统计
+    Protocols,
+    /// 每个 IP 的 EWMA 速率与包间隔分布
+    Timing,
+}
+
+impl StatsDetail {
+    fn bit(self) -> u32 {
+        match self {
+            StatsDetail::Peers => STATS_PEERS,
+            StatsDetail::Protocols => STATS_PROTOCOLS,
+            StatsDetail::Timing => STATS_TIMING,
+        }
+    }
+}
+
+/// eBPF 程序的加载方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum AttachMode {
+    /// 由 tc 加载并附加程序
+    Internal,
+    /// 由其他进程加载程序并固定 Map，tc 只从 --pin-path 读取统计
+    External,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// 检查 .env 配置文件与接口后退出，不加载 eBPF 程序
+    CheckConfig {
+        /// 配置文件路径
+        file: PathBuf,
+    },
+}
+
 #[derive(Debug, Parser)]
 struct Opt {
-    #[clap(short, long, default_value = "eth0")]
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// 控制台输出语言
+    #[clap(long, value_enum, default_value = "zh", global = true)]
+    lang: Lang,
+
+    /// 网络接口，auto 表示自动选择默认路由所在的接口
+    #[clap(short, long, default_value = "eth0", global = true)]
     iface: String,
+
+    /// 出站/入站字节比例超过该值时告警（疑似数据外泄）
+    #[clap(long, default_value = "10.0")]
+    exfil_ratio: f64,
+
+    /// 比例告警所需的最小出站字节数，避免低流量噪声
+    #[clap(long, default_value = "1048576")]
+    exfil_min_bytes: u64,
+
+    /// 抓取与该 IP 相关的数据包头部写入 pcap 文件（默认关闭）
+    #[clap(long)]
+    capture_ip: Option<Ipv4Addr>,
+
+    /// 抓包端口过滤，0 表示任意端口
+    #[clap(long, default_value = "0")]
+    capture_port: u16,
+
+    /// 每秒最多抓取的数据包数
+    #[clap(long, default_value = "100")]
+    capture_rate: u32,
+
+    /// 抓包输出文件
+    #[clap(long, default_value = "tc-capture.pcap")]
+    capture_file: String,
+
+    /// 配置了 TARGET_PORT 时，端口与 IP 的组合方式
+    #[clap(long, value_enum, default_value = "and")]
+    port_policy: PortPolicy,
+
+    /// 采样一次后输出统计并退出
+    #[clap(long)]
+    once: bool,
+
+    /// --once 模式下的采样时长（秒）
+    #[clap(long, default_value = "5")]
+    sample_duration: u64,
+
+    /// 每个周期读取 /proc/net/dev 与 tc 的统计进行对比
+    #[clap(long)]
+    interface_stats: bool,
+
+    /// 以该间隔（毫秒）采样接口接收字节数，输出每个展示周期内的突发峰值速率（默认关闭）
+    #[clap(long, value_parser = clap::value_parser!(u64).range(10..))]
+    burst_sample_ms: Option<u64>,
+
+    /// 输出中隐藏 IP 地址的主机位（内核统计不受影响）
+    #[clap(long)]
+    anonymize: bool,
+
+    /// --anonymize 时保留的前缀长度
+    #[clap(long, default_value = "24", value_parser = clap::value_parser!(u8).range(0..=32))]
+    anonymize_prefix: u8,
+
+    /// 字节统计口径
+    #[clap(long, value_enum, default_value = "ip")]
+    packet_length: PacketLength,
+
+    /// 在 --packet-length 之外每个数据包额外计入的字节数，例如 VLAN 标签为 4
+    #[clap(long, default_value = "0")]
+    frame_overhead: u32,
+
+    /// OR 策略下仅端口匹配的非目标 IP 按字节数只展示前 N 个（仍全部计入统计与导出）
+    #[clap(long, default_value = "10")]
+    port_only_top: usize,
+
+    /// 总字节数低于该值的 IP 不在控制台展示（仍计入统计）
+    #[clap(long, default_value = "0")]
+    min_bytes: u64,
+
+    /// 总包数低于该值的 IP 不在控制台展示（仍计入统计）
+    #[clap(long, default_value = "0")]
+    min_packets: u64,
+
+    /// 按入口接口指定方向，例如 eth0=inbound，可重复；程序会同时附加到这些接口
+    #[clap(long, value_parser = parse_iface_direction)]
+    direction_iface: Vec<IfaceDirection>,
+
+    /// 定期将 TRAFFIC_STATS 快照写入该目录（默认关闭）
+    #[clap(long)]
+    export_dir: Option<PathBuf>,
+
+    /// 导出间隔（秒），与控制台展示周期无关
+    #[clap(long, default_value = "60", value_parser = clap::value_parser!(u64).range(1..))]
+    export_interval: u64,
+
+    /// 校对目标 Map 与当前配置的间隔（秒），补回被外部修改的条目；0 表示关闭
+    #[clap(long, default_value = "30")]
+    reconcile_interval: u64,
+
+    /// 导出文件格式
+    #[clap(long, value_enum, default_value = "json")]
+    export_format: ExportFormat,
+
+    /// 导出目录中最多保留的快照文件数
+    #[clap(long, default_value = "24", value_parser = clap::value_parser!(u64).range(1..))]
+    export_keep: u64,
+
+    /// 退出时将统计写入该文件，下次启动时从中恢复（默认关闭）
+    #[clap(long)]
+    state_file: Option<PathBuf>,
+
+    /// 关闭的统计项，逗号分隔，可减少每个数据包的 Map 更新（包数与字节数始终统计）
+    #[clap(long, value_enum, value_delimiter = ',')]
+    disable_stats: Vec<StatsDetail>,
+
+    /// 低开销模式，等同于 --disable-stats peers,protocols,timing
+    #[clap(long, conflicts_with = "disable_stats")]
+    lite: bool,
+
+    /// eBPF 程序的加载方式
+    #[clap(long, value_enum, default_value = "internal")]
+    attach_mode: AttachMode,
+
+    /// --attach-mode external 时固定 Map 所在的 bpffs 目录
+    #[clap(long, default_value = "/sys/fs/bpf/tc")]
+    pin_path: PathBuf,
+}
+
+// 输出用的 IP 字符串，开启 --anonymize 时隐藏主机位
+fn format_ip(ip: u32, opt: &Opt) -> String {
+    if opt.anonymize {
+        format!(
+            "{}/{}",
+            anonymize_ip(u32_to_ip(ip), opt.anonymize_prefix),
+            opt.anonymize_prefix
+        )
+    } else {
+        u32_to_ip(ip).to_string()
+    }
+}
+
+fn format_ipv6(ip: Ipv6Addr, opt: &Opt) -> String {
+    if opt.anonymize {
+        format!(
+            "{}/{}",
+            anonymize_ipv6(ip, opt.anonymize_prefix),
+            opt.anonymize_prefix
+        )
+    } else {
+        ip.to_string()
+    }
+}
+
+// 比匿名前缀更细的 EXCLUDE_IP 网段按匿名前缀输出
+fn format_exclude(net: &ExcludeNet, opt: &Opt) -> String {
+    if opt.anonymize && net.prefix_len > opt.anonymize_prefix {
+        format_ip(net.addr, opt)
+    } else {
+        net.to_string()
+    }
+}
+
+fn format_overlap(overlap: &ExcludeOverlap, opt: &Opt) -> String {
+    let covered = format_exclude(&overlap.covered, opt);
+    if overlap.covered == overlap.by {
+        t!(exclude_duplicate, covered)
+    } else {
+        t!(exclude_covered, covered, format_exclude(&overlap.by, opt))
+    }
+}
+
+// 带标签的 IP 字符串，例如 "10.0.0.5 (db-primary)"
+fn format_target(ip: u32, targets: &Targets, opt: &Opt) -> String {
+    match targets.labels.get(&ip) {
+        Some(label) => format!("{} ({})", format_ip(ip, opt), label),
+        None => format_ip(ip, opt),
+    }
+}
+
+// 写入 SETTINGS 的 disabled_stats 位掩码
+fn disabled_stats(opt: &Opt) -> u32 {
+    if opt.lite {
+        return STATS_PEERS | STATS_PROTOCOLS | STATS_TIMING;
+    }
+    opt.disable_stats
+        .iter()
+        .fold(0, |mask, detail| mask | detail.bit())
+}
+
+fn stats_enabled(opt: &Opt, stat: u32) -> bool {
+    disabled_stats(opt) & stat == 0
+}
+
+fn below_display_threshold(stats: &TrafficStats, opt: &Opt) -> bool {
+    stats.total_bytes() < opt.min_bytes || stats.total_packets() < opt.min_packets
+}
+
+fn is_exfil_suspect(stats: &TrafficStats, opt: &Opt) -> bool {
+    stats.outbound_bytes >= opt.exfil_min_bytes && stats.outbound_ratio() > opt.exfil_ratio
 }
 
+fn display_ip_stats(
+    ip_addr: &str,
+    stats: &TrafficStats,
+    totals: &TrafficStats,
+    distinct_peers: u64,
+    opt: &Opt,
+) {
+    let kb = |bytes: u64| format!("{:.2}", bytes as f64 / 1024.0);
+
+    info!("{}", t!(traffic_header, ip_addr));
+    info!("{}", t!(inbound_title));
+    info!("{}", t!(packets_line, stats.inbound_packets));
+    info!(
+        "{}",
+        t!(bytes_line, stats.inbound_bytes, kb(stats.inbound_bytes))
+    );
+    info!("{}", t!(outbound_title));
+    info!("{}", t!(packets_line, stats.outbound_packets));
+    info!(
+        "{}",
+        t!(bytes_line, stats.outbound_bytes, kb(stats.outbound_bytes))
+    );
+    info!("{}", t!(total_title));
+    info!("{}", t!(packets_line, stats.total_packets()));
+    info!(
+        "{}",
+        t!(bytes_line, stats.total_bytes(), kb(stats.total_bytes()))
+    );
+    info!(
+        "{}",
+        t!(
+            share_line,
+            format!(
+                "{:.2}",
+                percent_of(stats.total_bytes(), totals.total_bytes())
+            ),
+            format!(
+                "{:.2}",
+                percent_of(stats.total_packets(), totals.total_packets())
+            )
+        )
+    );
+    if stats_enabled(opt, STATS_TIMING) {
+        info!("{}", t!(rate_line, kb(stats.byte_rate(monotonic_ns()))));
+        info!(
+            "{}",
+            t!(
+                gap_line,
+                stats.gap_buckets[0],
+                stats.gap_buckets[1],
+                stats.gap_buckets[2],
+                stats.gap_buckets[3],
+                format!("{:.1}", stats.slow_gap_ratio() * 100.0)
+            )
+        );
+    }
+    if stats_enabled(opt, STATS_PEERS) {
+        info!("{}", t!(peers_line, distinct_peers));
+    }
+    info!(
+        "{}",
+        t!(ratio_line, format!("{:.2}", stats.outbound_ratio()))
+    );
+    if is_exfil_suspect(stats, opt) {
+        warn!(
+            "{}",
+            t!(
+                exfil_warning,
+                ip_addr,
+                format!("{:.2}", stats.outbound_ratio()),
+                format!("{:.2}", opt.exfil_ratio)
+            )
+        );
+    }
+    info!("================================\n");
+}
+
+fn display_diagnostics(diagnostics: &PerCpuArray<MapData, Diagnostics>) -> anyhow::Result<()> {
+    let mut total = Diagnostics::default();
+    for per_cpu in diagnostics.get(&0, 0)?.iter() {
+        total.add(per_cpu);
+    }
+
+    info!(
+        "{}",
+        t!(
+            diagnostics_line,
+            total.fragmented_packets,
+            total.malformed_packets,
+            total.port_only_dropped,
+            total.insert_failed
+        )
+    );
+
+    Ok(())
+}
+
+fn display_protocol_stats(
+    protocol_stats: &PerCpuArray<MapData, ProtocolStats>,
+) -> anyhow::Result<()> {
+    let mut entries = Vec::new();
+    for protocol in 0..MAX_PROTOCOLS {
+        let mut total = ProtocolStats::default();
+        for per_cpu in protocol_stats.get(&protocol, 0)?.iter() {
+            total.packets += per_cpu.packets;
+            total.bytes += per_cpu.bytes;
+        }
+        if total.packets > 0 {
+            entries.push(t!(
+                protocol_entry,
+                protocol_name(protocol as u8),
+                total.packets,
+                total.bytes
+            ));
+        }
+    }
+
+    if entries.is_empty() {
+        entries.push("-".to_string());
+    }
+    info!("{}", t!(protocol_line, entries.join(", ")));
+
+    Ok(())
+}
+
+fn distinct_peers(peer_stats: &HashMap<MapData, u32, PeerRegisters>, ip: u32) -> u64 {
+    peer_stats
+        .get(&ip, 0)
+        .map(|peers| estimate_distinct_peers(&peers))
+        .unwrap_or(0)
+}
+
+// TRAFFIC_STATS 中全部 IP 的导出记录，以及本次读取的 Map 条目数
+fn export_records(
+    maps: &StatsMaps,
+    targets: &Targets,
+    opt: &Opt,
+) -> anyhow::Result<(Vec<ExportRecord>, usize)> {
+    let now = monotonic_ns();
+    let (totals, mut reads) = traffic_totals(&maps.traffic)?;
+    let mut records = Vec::new();
+    for entry in maps.traffic.iter() {
+        let (ip, stats) = entry?;
+        // TRAFFIC_STATS 条目与对应的 PEER_STATS 查找
+        reads += 2;
+        records.push(ExportRecord {
+            ip: format_ip(ip, opt),
+            label: targets.labels.get(&ip).cloned(),
+            stats,
+            byte_rate: stats.byte_rate(now),
+            distinct_peers: distinct_peers(&maps.peer_stats, ip),
+            percent_of_total_bytes: percent_of(stats.total_bytes(), totals.total_bytes()),
+            percent_of_total_packets: percent_of(stats.total_packets(), totals.total_packets()),
+        });
+    }
+    Ok((records, reads))
+}
+
+// 所有 IPv4 数据包按协议各计一次，不受目标数量与方向影响，可直接与接口接收字节数对比
+fn protocol_bytes(protocol_stats: &PerCpuArray<MapData, ProtocolStats>) -> anyhow::Result<u64> {
+    let mut bytes = 0;
+    for protocol in 0..MAX_PROTOCOLS {
+        for per_cpu in protocol_stats.get(&protocol, 0)?.iter() {
+            bytes += per_cpu.bytes;
+        }
+    }
+    Ok(bytes)
+}
+
+// 无法保证 PROTOCOL_STATS 字节数不超过接口 RX 字节数时跳过对比
+fn cross_check_unsupported(opt: &Opt) -> Option<&'static str> {
+    if opt.attach_mode == AttachMode::External {
+        Some("--attach-mode external")
+    } else if !opt.direction_iface.is_empty() {
+        Some("--direction-iface")
+    } else if matches!(
+        opt.packet_length,
+        PacketLength::Ethernet | PacketLength::Line
+    ) {
+        Some("--packet-length ethernet/line")
+    } else if opt.frame_overhead != 0 {
+        Some("--frame-overhead")
+    } else if !stats_enabled(opt, STATS_PROTOCOLS) {
+        Some("--disable-stats protocols / --lite")
+    } else {
+        None
+    }
+}
+
+// tc 记录的全部流量（所有 TRAFFIC_STATS 条目的计数器之和），作为各 IP 占比的分母，同时返回遍历的条目数
+fn traffic_totals(
+    traffic_map: &HashMap<MapData, u32, TrafficStats>,
+) -> anyhow::Result<(TrafficStats, usize)> {
+    let mut totals = TrafficStats::new();
+    let mut entries = 0;
+    for entry in traffic_map.iter() {
+        let (_, stats) = entry?;
+        entries += 1;
+        totals.inbound_packets += stats.inbound_packets;
+        totals.inbound_bytes += stats.inbound_bytes;
+        totals.outbound_packets += stats.outbound_packets;
+        totals.outbound_bytes += stats.outbound_bytes;
+    }
+    Ok((totals, entries))
+}
+
+// 返回本次读取的 Map 条目数：遍历到的每个条目与每次查找各计一次，PerCpuArray 的一次查找计一次
 async fn display_traffic_stats(
-    traffic_map: &HashMap<&aya::maps::MapData, u32, TrafficStats>,
-    target_ip: &Vec<TargetIp>,
-) -> Result<(), anyhow::Error> {
-    for ip in target_ip {
-        if let Ok(stats) = traffic_map.get(&ip.0, 0) {
-            let ip_addr = ip.to_string();
-            info!("\n=== 流量统计 for {} ===", ip_addr);
-            info!("入站流量:");
-            info!("  数据包: {} 个", stats.inbound_packets);
-            info!(
-                "  字节数: {} bytes ({:.2} KB)",
-                stats.inbound_bytes,
-                stats.inbound_bytes as f64 / 1024.0
-            );
-            info!("出站流量:");
-            info!("  数据包: {} 个", stats.outbound_packets);
-            info!(
-                "  字节数: {} bytes ({:.2} KB)",
-                stats.outbound_bytes,
-                stats.outbound_bytes as f64 / 1024.0
-            );
-            info!("总计:");
-            info!("  数据包: {} 个", stats.total_packets());
-            info!(
-                "  字节数: {} bytes ({:.2} KB)",
-                stats.total_bytes(),
-                stats.total_bytes() as f64 / 1024.0
+    maps: &StatsMaps,
+    targets: &Targets,
+    opt: &Opt,
+) -> Result<usize, anyhow::Error> {
+    let (totals, mut reads) = traffic_totals(&maps.traffic)?;
+    let mut hidden = 0;
+    for ip in &targets.ips {
+        reads += 1;
+        if let Ok(stats) = maps.traffic.get(&ip.0, 0) {
+            if below_display_threshold(&stats, opt) {
+                hidden += 1;
+                continue;
+            }
+            reads += 1;
+            display_ip_stats(
+                &format_target(ip.0, targets, opt),
+                &stats,
+                &totals,
+                distinct_peers(&maps.peer_stats, ip.0),
+                opt,
             );
-            info!("================================\n");
         } else {
-            info!("没有找到 {} 的流量统计数据", ip.to_string());
+            info!("{}", t!(no_stats, format_target(ip.0, targets, opt)));
         }
     }
 
-    Ok(())
+    // OR 策略下，仅端口命中的非目标 IP 也会被统计，按字节数只展示前 --port-only-top 个
+    if targets.port_filter == PORT_FILTER_OR {
+        let mut port_only = Vec::new();
+        for entry in maps.traffic.iter() {
+            let (ip, stats) = entry?;
+            reads += 1;
+            if targets.ips.iter().any(|target| target.0 == ip) {
+                continue;
+            }
+            if below_display_threshold(&stats, opt) {
+                hidden += 1;
+                continue;
+            }
+            port_only.push((ip, stats));
+        }
+        port_only.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.total_bytes()));
+
+        let shown = port_only.len().min(opt.port_only_top);
+        for (ip, stats) in &port_only[..shown] {
+            reads += 1;
+            display_ip_stats(
+                &format_target(*ip, targets, opt),
+                stats,
+                &totals,
+                distinct_peers(&maps.peer_stats, *ip),
+                opt,
+            );
+        }
+        if port_only.len() > shown {
+            info!("{}", t!(port_only_hidden, port_only.len() - shown, shown));
+        }
+    }
+
+    if hidden > 0 {
+        info!("{}", t!(hidden_ips, hidden));
+    }
+
+    if stats_enabled(opt, STATS_PROTOCOLS) {
+        display_protocol_stats(&maps.protocol_stats)?;
+        reads += MAX_PROTOCOLS as usize;
+    }
+    display_diagnostics(&maps.diagnostics)?;
+    reads += 1;
+
+    Ok(reads)
 }
 
-#[tokio::main]
-async fn main() -> Result<(), anyhow::Error> {
-    dotenv::dotenv().ok();
+// 退出前保存 TRAFFIC_STATS / PEER_STATS / DIAGNOSTICS / PROTOCOL_STATS，供下次启动恢复
+fn save_state(path: &Path, maps: &StatsMaps) {
+    let result = MapState::read_maps(maps).and_then(|state| state.save(path));
+    match result {
+        Ok(()) => info!("{}", t!(state_saved, path.display())),
+        Err(e) => warn!("{}", t!(state_save_failed, format!("{e:#}"))),
+    }
+}
 
-    let opt = Opt::parse();
+fn port_filter_for(target_port: &TargetPorts, policy: PortPolicy) -> u32 {
+    match (target_port.is_empty(), policy) {
+        (true, _) => PORT_FILTER_NONE,
+        (false, PortPolicy::And) => PORT_FILTER_AND,
+        (false, PortPolicy::Or) => PORT_FILTER_OR,
+    }
+}
 
-    env_logger::Builder::from_default_env()
-        .filter_level(LevelFilter::Info)
-        .init();
+// IPv6 目标暂不支持，逐个提示后只保留 IPv4
+fn ipv4_targets(target_ips: TargetIps, opt: &Opt) -> Vec<TargetIp> {
+    for ip in &target_ips.ipv6 {
+        warn!("{}", t!(ipv6_target_skipped, format_ipv6(*ip, opt)));
+    }
+    target_ips.ipv4
+}
 
-    // Bump the memlock rlimit. This is needed for older kernels that don't use the
-    // new memcg based accounting, see https://lwn.net/Articles/837122/
-    let rlim = libc::rlimit {
-        rlim_cur: libc::RLIM_INFINITY,
-        rlim_max: libc::RLIM_INFINITY,
+// 合并重复与被包含的 EXCLUDE_IP 条目，LPM 中只保留实际生效的网段
+fn effective_excludes(exclude_ip: Vec<ExcludeNet>, opt: &Opt) -> Vec<ExcludeNet> {
+    let (exclude_ip, overlaps) = collapse_excludes(exclude_ip);
+    for overlap in &overlaps {
+        warn!("{}", format_overlap(overlap, opt));
+    }
+    exclude_ip
+}
+
+fn log_target_changes(changes: &TargetChanges, targets: &Targets, opt: &Opt) {
+    for ip in &changes.added_ips {
+        info!(
+            "insert xdp_target_ip_map: {}",
+            format_target(ip.0, targets, opt)
+        );
+    }
+    for ip in &changes.removed_ips {
+        info!(
+            "remove xdp_target_ip_map: {}",
+            format_target(ip.0, targets, opt)
+        );
+    }
+    for port in &changes.added_ports {
+        info!("insert xdp_target_port_map: {}", port);
+    }
+    for port in &changes.removed_ports {
+        info!("remove xdp_target_port_map: {}", port);
+    }
+    for net in &changes.added_excludes {
+        info!("insert xdp_exclude_ip_map: {}", format_exclude(net, opt));
+    }
+    for net in &changes.removed_excludes {
+        info!("remove xdp_exclude_ip_map: {}", format_exclude(net, opt));
+    }
+    if (changes.port_filter_changed || changes.ranges_changed) && !targets.ports.is_empty() {
+        info!(
+            "{}",
+            t!(port_filter, format!("{:?}", opt.port_policy), targets.ports)
+        );
+    }
+}
+
+fn log_target_drift(drift: &TargetDrift, targets: &Targets, opt: &Opt) {
+    for ip in &drift.missing_ips {
+        warn!(
+            "{}",
+            t!(
+                drift_missing,
+                "TARGET_IP",
+                format_target(ip.0, targets, opt)
+            )
+        );
+    }
+    for ip in &drift.unexpected_ips {
+        warn!(
+            "{}",
+            t!(
+                drift_unexpected,
+                "TARGET_IP",
+                format_target(ip.0, targets, opt)
+            )
+        );
+    }
+    for port in &drift.missing_ports {
+        warn!("{}", t!(drift_missing, "TARGET_PORT", port));
+    }
+    for port in &drift.unexpected_ports {
+        warn!("{}", t!(drift_unexpected, "TARGET_PORT", port));
+    }
+    for net in &drift.missing_excludes {
+        warn!(
+            "{}",
+            t!(drift_missing, "EXCLUDE_IP", format_exclude(net, opt))
+        );
+    }
+    for net in &drift.unexpected_excludes {
+        warn!(
+            "{}",
+            t!(drift_unexpected, "EXCLUDE_IP", format_exclude(net, opt))
+        );
+    }
+    if drift.ranges_rewritten {
+        warn!("{}", t!(drift_rewritten, "PORT_RANGES"));
+    }
+    if drift.port_filter_rewritten {
+        warn!("{}", t!(drift_rewritten, "PORT_FILTER"));
+    }
+}
+
+// 支持 SIGHUP 热更新的配置项
+const RELOADABLE_KEYS: [&str; 4] = ["TARGET_IP", "TARGET_PORT", "TARGET_LABEL", "EXCLUDE_IP"];
+
+// 加载 .env 之前的真实环境变量；dotenv 会把 .env 的值写入进程环境，之后无法再区分来源
+fn reloadable_env() -> std::collections::HashMap<String, String> {
+    RELOADABLE_KEYS
+        .iter()
+        .filter_map(|key| env::var(key).ok().map(|value| (key.to_string(), value)))
+        .collect()
+}
+
+// SIGHUP 时重新读取 .env，仅 TARGET_IP / TARGET_PORT / TARGET_LABEL / EXCLUDE_IP 支持热更新，命令行参数需重启生效
+fn reload_targets(
+    targets: &mut Targets,
+    env_path: Option<&Path>,
+    startup_env: &std::collections::HashMap<String, String>,
+    opt: &Opt,
+) -> anyhow::Result<()> {
+    let values = match env_path {
+        Some(path) => read_env_file(path)?,
+        None => Default::default(),
     };
-    let ret = unsafe { libc::setrlimit(libc::RLIMIT_MEMLOCK, &rlim) };
-    if ret != 0 {
-        debug!("remove limit on locked memory failed, ret is: {ret}");
+    // .env 中的值优先；.env 中没有的配置项取加载 .env 之前的环境变量，两者都没有则视为空，
+    // 因此从 .env 删除的配置项会被清空，而不是沿用启动时从 .env 读到的旧值
+    let value = |key: &str| {
+        values
+            .get(key)
+            .or_else(|| startup_env.get(key))
+            .cloned()
+            .unwrap_or_default()
+    };
+
+    let target_ip = ipv4_targets(parse_target_ip(&value("TARGET_IP"))?, opt);
+    let target_port = parse_target_port(&value("TARGET_PORT"))?;
+    let target_label = parse_target_label(&value("TARGET_LABEL"))?;
+    let exclude_ip = effective_excludes(parse_exclude_ip(&value("EXCLUDE_IP"))?, opt);
+    let port_filter = port_filter_for(&target_port, opt.port_policy);
+
+    let labels_changed = targets.labels != target_label;
+    targets.check_exclude_capacity(&exclude_ip)?;
+    let mut changes = targets.apply(target_ip, target_port, port_filter)?;
+    targets.apply_excludes(exclude_ip, &mut changes)?;
+    // 目标与排除网段都更新成功后才切换标签，被拒绝的重新加载不改变任何配置
+    targets.labels = target_label;
+
+    if labels_changed {
+        info!("{}", t!(labels_updated, targets.labels.len()));
+    }
+    if changes.is_empty() && !labels_changed {
+        info!("{}", t!(config_unchanged));
+    } else {
+        log_target_changes(&changes, targets, opt);
+        if changes.port_filter_changed && targets.ports.is_empty() {
+            info!("{}", t!(port_filter_disabled));
+        }
+    }
+
+    Ok(())
+}
+
+// 非目标条目占用仅端口匹配的名额：启动时（含从状态文件恢复的条目）与重新加载目标后按实际条目数校正计数
+fn sync_port_only_count(
+    bpf: &mut aya::Ebpf,
+    maps: &StatsMaps,
+    targets: &Targets,
+) -> anyhow::Result<()> {
+    let mut count = 0u64;
+    for key in maps.traffic.keys() {
+        let ip = key?;
+        if !targets.ips.iter().any(|target| target.0 == ip) {
+            count += 1;
+        }
     }
+    let mut port_only_count: Array<_, u64> =
+        Array::try_from(bpf.map_mut("PORT_ONLY_COUNT").unwrap())?;
+    port_only_count.set(0, count, 0)?;
+    Ok(())
+}
 
+// 加载并附加 XDP 程序，写入接口方向、运行时设置与抓包配置
+fn load_program(opt: &Opt) -> anyhow::Result<aya::Ebpf> {
     // This will include your eBPF object file as raw bytes at compile-time and load it at
     // runtime. This approach is recommended for most real-world use cases. If you would
     // like to specify the eBPF program at runtime rather than at compile-time, you can
     // reach for `Ebpf::load_file` instead.
     let mut bpf = aya::Ebpf::load(aya::include_bytes_aligned!(concat!(env!("OUT_DIR"), "/tc")))?;
-    if let Err(e) = EbpfLogger::init(&mut bpf) {
+    // eBPF 日志逐包输出原始 IP，开启 --anonymize 时不转发
+    if opt.anonymize {
+        info!("{}", t!(anonymize_enabled));
+    } else if let Err(e) = EbpfLogger::init(&mut bpf) {
         // This can happen if you remove all log statements from your eBPF program.
         warn!("failed to initialize eBPF logger: {e}");
     }
 
+    // 在附加程序前恢复，避免新数据包的计数被覆盖
+    if let Some(path) = opt.state_file.as_deref().filter(|path| path.exists()) {
+        match MapState::load(path).and_then(|state| state.restore(&mut bpf).map(|()| state)) {
+            Ok(state) => info!(
+                "{}",
+                t!(state_restored, state.traffic.len(), path.display())
+            ),
+            Err(e) => warn!("{}", t!(state_restore_failed, format!("{e:#}"))),
+        }
+    }
+
     let program: &mut Xdp = bpf.program_mut("xdp_firewall").unwrap().try_into()?;
     program.load()?;
     program.attach(&opt.iface, XdpFlags::default())
         .context("failed to attach the XDP program with default flags - try changing XdpFlags::default() to XdpFlags::SKB_MODE")?;
+    for iface_direction in &opt.direction_iface {
+        if iface_direction.iface != opt.iface {
+            program
+                .attach(&iface_direction.iface, XdpFlags::default())
+                .with_context(|| {
+                    format!(
+                        "failed to attach the XDP program to {}",
+                        iface_direction.iface
+                    )
+                })?;
+        }
+    }
+
+    let mut iface_direction_map: HashMap<_, u32, u32> =
+        HashMap::try_from(bpf.map_mut("IFACE_DIRECTION").unwrap())?;
+    for iface_direction in &opt.direction_iface {
+        let index = ifindex(&iface_direction.iface)?;
+        iface_direction_map.insert(index, iface_direction.direction, 0)?;
+        let direction = if iface_direction.direction == DIRECTION_INBOUND {
+            t!(inbound)
+        } else {
+            t!(outbound)
+        };
+        info!(
+            "{}",
+            t!(iface_direction, iface_direction.iface, index, direction)
+        );
+    }
 
-    let target_ip = get_target_ip()?;
+    let (length_mode, length_overhead) = opt.packet_length.settings();
+    let mut settings_map: Array<_, Settings> = Array::try_from(bpf.map_mut("SETTINGS").unwrap())?;
+    settings_map.set(
+        0,
+        Settings {
+            length_mode,
+            disabled_stats: disabled_stats(opt),
+            length_overhead: length_overhead + opt.frame_overhead,
+        },
+        0,
+    )?;
 
-    let mut xdp_target_ip_map: HashMap<_, u32, u8> =
-        HashMap::try_from(bpf.map_mut("TARGET_IP").unwrap())?;
+    if let Some(capture_ip) = opt.capture_ip {
+        let mut capture_config: Array<_, CaptureConfig> =
+            Array::try_from(bpf.map_mut("CAPTURE_CONFIG").unwrap())?;
+        capture_config.set(
+            0,
+            CaptureConfig {
+                ipv4_address: u32::from(capture_ip),
+                port: opt.capture_port,
+                enabled: 1,
+                _pad: 0,
+                max_per_sec: opt.capture_rate,
+            },
+            0,
+        )?;
 
-    for ip in target_ip.clone() {
+        let ring = RingBuf::try_from(bpf.take_map("CAPTURE_RING").unwrap())?;
+        let path = opt.capture_file.clone();
         info!(
-            "insert xdp_target_ip_map: {:?}, to: {:?}",
-            ip.0,
-            ip.to_string()
+            "{}",
+            t!(
+                capture_enabled,
+                format_ip(u32::from(capture_ip), opt),
+                opt.capture_port,
+                path
+            )
         );
-        xdp_target_ip_map.insert(&ip.0, &1u8, 0)?;
+        tokio::spawn(async move {
+            if let Err(e) = capture::run_capture(ring, path).await {
+                warn!("{}", t!(capture_error, e));
+            }
+        });
     }
 
-    // 获取流量统计Map
-    let traffic_map: HashMap<_, u32, TrafficStats> =
-        HashMap::try_from(bpf.map("TRAFFIC_STATS").unwrap())?;
+    Ok(bpf)
+}
+
+// 外部加载模式下只读取统计，这些写入程序配置的参数不会生效
+fn external_ignored_options(opt: &Opt) -> Vec<&'static str> {
+    let mut ignored = Vec::new();
+    if opt.capture_ip.is_some() {
+        ignored.push("--capture-ip");
+    }
+    if !opt.direction_iface.is_empty() {
+        ignored.push("--direction-iface");
+    }
+    if opt.state_file.is_some() {
+        ignored.push("--state-file");
+    }
+    if opt.packet_length != PacketLength::Ip {
+        ignored.push("--packet-length");
+    }
+    if opt.frame_overhead != 0 {
+        ignored.push("--frame-overhead");
+    }
+    if disabled_stats(opt) != 0 {
+        ignored.push("--disable-stats / --lite");
+    }
+    ignored
+}
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    let startup_env = reloadable_env();
+    let env_path = dotenv::dotenv().ok();
+
+    let mut opt = Opt::parse();
+    i18n::set_lang(opt.lang);
+
+    if let Some(Command::CheckConfig { file }) = &opt.command {
+        return check_config::check_config(file, &opt.iface);
+    }
+
+    env_logger::Builder::from_default_env()
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    let iface = resolve_interface(&opt.iface)?;
+    if opt.iface == AUTO_IFACE {
+        info!("{}", t!(iface_auto, iface));
+    }
+    opt.iface = iface;
+
+    // Bump the memlock rlimit. This is needed for older kernels that don't use the
+    // new memcg based accounting, see https://lwn.net/Articles/837122/
+    let rlim = libc::rlimit {
+        rlim_cur: libc::RLIM_INFINITY,
+        rlim_max: libc::RLIM_INFINITY,
+    };
+    let ret = unsafe { libc::setrlimit(libc::RLIMIT_MEMLOCK, &rlim) };
+    if ret != 0 {
+        debug!("remove limit on locked memory failed, ret is: {ret}");
+    }
+
+    // 内部加载时 Ebpf 被释放会卸载程序，需持有到退出
+    let (mut bpf, maps, mut targets) = match opt.attach_mode {
+        AttachMode::Internal => {
+            let mut bpf = load_program(&opt)?;
+            let maps = StatsMaps::from_ebpf(&mut bpf)?;
+            let mut targets = Targets::new(&mut bpf)?;
+            targets.labels = get_target_label()?;
+            let target_port = get_target_port()?;
+            let port_filter = port_filter_for(&target_port, opt.port_policy);
+            let exclude_ip = effective_excludes(get_exclude_ip()?, &opt);
+            let mut changes = targets.apply(
+                ipv4_targets(get_target_ip()?, &opt),
+                target_port,
+                port_filter,
+            )?;
+            targets.apply_excludes(exclude_ip, &mut changes)?;
+            sync_port_only_count(&mut bpf, &maps, &targets)?;
+            log_target_changes(&changes, &targets, &opt);
+            info!("{}", t!(attached, opt.iface));
+            (Some(bpf), maps, targets)
+        }
+        AttachMode::External => {
+            let ignored = external_ignored_options(&opt);
+            if !ignored.is_empty() {
+                warn!("{}", t!(external_ignored, ignored.join(", ")));
+            }
+            let maps = StatsMaps::from_pins(&opt.pin_path)?;
+            let mut targets = Targets::from_pins(&opt.pin_path)?;
+            targets.labels = get_target_label()?;
+            info!("{}", t!(external_opened, opt.pin_path.display()));
+            (None, maps, targets)
+        }
+    };
 
-    info!("XDP程序已加载并附加到 {} 接口", opt.iface);
     info!(
-        "开始监控 [{}] 的流量...",
-        target_ip
-            .iter()
-            .map(|ip| ip.to_string())
-            .collect::<Vec<String>>()
-            .join(", ")
+        "{}",
+        t!(
+            monitoring,
+            targets
+                .ips
+                .iter()
+                .map(|ip| format_target(ip.0, &targets, &opt))
+                .collect::<Vec<String>>()
+                .join(", ")
+        )
     );
-    info!("按 Ctrl-C 退出");
+
+    // 外部加载时状态归加载方管理，不恢复也不保存
+    let state_file = opt
+        .state_file
+        .as_deref()
+        .filter(|_| opt.attach_mode == AttachMode::Internal);
+
+    if opt.once {
+        info!("{}", t!(once_sampling, opt.sample_duration));
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(opt.sample_duration)) => {}
+            _ = signal::ctrl_c() => {
+                info!("{}", t!(once_interrupted));
+            }
+        }
+        display_traffic_stats(&maps, &targets, &opt).await?;
+        if let Some(path) = state_file {
+            save_state(path, &maps);
+        }
+        return Ok(());
+    }
+
+    info!("{}", t!(running_hint));
+
+    let mut hangup = signal::unix::signal(SignalKind::hangup())?;
+
+    let mut cross_check = match (opt.interface_stats, cross_check_unsupported(&opt)) {
+        (false, _) => None,
+        (true, Some(reason)) => {
+            warn!("{}", t!(interface_stats_skipped, reason));
+            None
+        }
+        (true, None) => Some(InterfaceCrossCheck::new(&opt.iface)),
+    };
+
+    let burst = opt.burst_sample_ms.map(|ms| {
+        info!("{}", t!(burst_enabled, ms, opt.iface));
+        BurstSampler::spawn(&opt.iface, Duration::from_millis(ms))
+    });
+
+    let exporter = match &opt.export_dir {
+        Some(dir) => {
+            info!(
+                "{}",
+                t!(
+                    export_enabled,
+                    opt.export_interval,
+                    dir.display(),
+                    format!("{:?}", opt.export_format),
+                    opt.export_keep
+                )
+            );
+            Some(Exporter::new(
+                dir,
+                opt.export_format,
+                opt.export_keep as usize,
+            )?)
+        }
+        None => None,
+    };
+
+    // 展示、导出与校对各自使用独立的定时器，互不影响
+    let mut display_interval = tokio::time::interval(DISPLAY_INTERVAL);
+    let mut export_interval = tokio::time::interval(Duration::from_secs(opt.export_interval));
+    let mut reconcile_interval =
+        tokio::time::interval(Duration::from_secs(opt.reconcile_interval.max(1)));
+    display_interval.tick().await;
+    export_interval.tick().await;
+    reconcile_interval.tick().await;
+    // 外部加载模式下目标 Map 由加载程序维护，不做校对
+    let reconcile = opt.reconcile_interval > 0 && opt.attach_mode == AttachMode::Internal;
+
+    let mut loop_stats = LoopStats::default();
+
+    // 接口断开期间 Map 不再更新，跳过展示以免把断开误认为没有流量
+    let mut iface_down = false;
 
     // 定期显示统计信息
     loop {
         tokio::select! {
-            _ = async {
-                tokio::task::spawn_blocking(|| std::thread::sleep(Duration::from_secs(5))).await
-            } => {
-                if let Err(e) = display_traffic_stats(&traffic_map, &target_ip).await {
-                    warn!("显示统计信息时出错: {}", e);
+            _ = display_interval.tick() => {
+                if let Some(state) = interface_down(&opt.iface) {
+                    if iface_down {
+                        warn!("{}", t!(iface_still_down, opt.iface, state));
+                    } else {
+                        warn!("{}", t!(iface_went_down, opt.iface, state));
+                        iface_down = true;
+                    }
+                    continue;
+                }
+                if iface_down {
+                    info!("{}", t!(iface_came_up, opt.iface));
+                    iface_down = false;
+                }
+                let started = Instant::now();
+                let mut entries = match display_traffic_stats(&maps, &targets, &opt).await {
+                    Ok(entries) => entries,
+                    Err(e) => {
+                        warn!("{}", t!(display_error, e));
+                        0
+                    }
+                };
+                if let Some(cross_check) = cross_check.as_mut() {
+                    entries += MAX_PROTOCOLS as usize;
+                    if let Err(e) = protocol_bytes(&maps.protocol_stats).and_then(|bytes| cross_check.check(bytes)) {
+                        warn!("{}", t!(interface_stats_error, e));
+                    }
+                }
+                if let Some(burst) = &burst {
+                    let peak_burst_bps = burst.take_peak_bps();
+                    info!(
+                        "{}",
+                        t!(burst_line, opt.iface, format!("{:.2}", peak_burst_bps as f64 / 1_000_000.0))
+                    );
+                }
+                loop_stats.record("display", started, entries, DISPLAY_INTERVAL);
+            }
+            _ = export_interval.tick(), if exporter.is_some() => {
+                if let Some(exporter) = &exporter {
+                    let started = Instant::now();
+                    let mut entries = 0;
+                    let result = export_records(&maps, &targets, &opt).and_then(|(records, reads)| {
+                        entries = reads;
+                        exporter.export(&records)
+                    });
+                    match result {
+                        Ok(path) => debug!("{}", t!(export_done, path.display())),
+                        Err(e) => warn!("{}", t!(export_error, format!("{e:#}"))),
+                    }
+                    loop_stats.record(
+                        "export",
+                        started,
+                        entries,
+                        Duration::from_secs(opt.export_interval),
+                    );
+                }
+            }
+            _ = reconcile_interval.tick(), if reconcile => {
+                match targets.reconcile() {
+                    Ok(drift) if drift.is_empty() => {}
+                    Ok(drift) => log_target_drift(&drift, &targets, &opt),
+                    Err(e) => warn!("{}", t!(reconcile_failed, format!("{e:#}"))),
+                }
+            }
+            _ = hangup.recv() => {
+                info!("{}", t!(sighup_received));
+                if opt.attach_mode == AttachMode::External {
+                    info!("{}", t!(reload_external));
+                } else if let Err(e) = reload_targets(&mut targets, env_path.as_deref(), &startup_env, &opt) {
+                    warn!("{}", t!(reload_failed, format!("{e:#}")));
+                } else if let Some(bpf) = bpf.as_mut() {
+                    // 新增或移除的目标会改变哪些已有条目算作仅端口匹配
+                    if let Err(e) = sync_port_only_count(bpf, &maps, &targets) {
+                        warn!("{}", t!(reload_failed, format!("{e:#}")));
+                    }
                 }
             }
             _ = signal::ctrl_c() => {
-                info!("收到 Ctrl-C 信号，正在退出...");
+                info!("{}", t!(shutdown));
                 // 最后显示一次统计信息
-                let _ = display_traffic_stats(&traffic_map, &target_ip).await;
+                let _ = display_traffic_stats(&maps, &targets, &opt).await;
+                if let Some(path) = state_file {
+                    save_state(path, &maps);
+                }
+                loop_stats.log_summary();
                 break;
             }
         }
@@ -0,0 +1,89 @@
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context};
+use aya::maps::{HashMap, Map, MapData, MapType, PerCpuArray};
+use aya::Ebpf;
+use tc_common::{Diagnostics, PeerRegisters, ProtocolStats, TrafficStats};
+
+// 用户态读取的统计 Map，来自本进程加载的程序或其他进程固定在 bpffs 中的 Map
+pub struct StatsMaps {
+    pub traffic: HashMap<MapData, u32, TrafficStats>,
+    pub diagnostics: PerCpuArray<MapData, Diagnostics>,
+    pub protocol_stats: PerCpuArray<MapData, ProtocolStats>,
+    pub peer_stats: HashMap<MapData, u32, PeerRegisters>,
+}
+
+impl StatsMaps {
+    pub fn from_ebpf(bpf: &mut Ebpf) -> anyhow::Result<Self> {
+        Ok(Self {
+            traffic: HashMap::try_from(bpf.take_map("TRAFFIC_STATS").unwrap())?,
+            diagnostics: PerCpuArray::try_from(bpf.take_map("DIAGNOSTICS").unwrap())?,
+            protocol_stats: PerCpuArray::try_from(bpf.take_map("PROTOCOL_STATS").unwrap())?,
+            peer_stats: HashMap::try_from(bpf.take_map("PEER_STATS").unwrap())?,
+        })
+    }
+
+    pub fn from_pins(dir: &Path) -> anyhow::Result<Self> {
+        Ok(Self {
+            traffic: typed(
+                open_pinned(dir, "TRAFFIC_STATS", MapType::Hash)?,
+                "TRAFFIC_STATS",
+            )?,
+            diagnostics: typed(
+                open_pinned(dir, "DIAGNOSTICS", MapType::PerCpuArray)?,
+                "DIAGNOSTICS",
+            )?,
+            protocol_stats: typed(
+                open_pinned(dir, "PROTOCOL_STATS", MapType::PerCpuArray)?,
+                "PROTOCOL_STATS",
+            )?,
+            peer_stats: typed(open_pinned(dir, "PEER_STATS", MapType::Hash)?, "PEER_STATS")?,
+        })
+    }
+}
+
+// 打开 dir/name 处固定的 Map，并确认其类型与本程序中的定义一致
+pub fn open_pinned(dir: &Path, name: &str, expected: MapType) -> anyhow::Result<Map> {
+    let path = dir.join(name);
+    if !path.exists() {
+        bail!("pinned map {} not found", path.display());
+    }
+    let data = MapData::from_pin(&path)
+        .with_context(|| format!("failed to open pinned map {}", path.display()))?;
+
+    let map_type = data.info()?.map_type()?;
+    if map_type != expected {
+        bail!(
+            "pinned map {} has type {:?}, expected {:?}",
+            path.display(),
+            map_type,
+            expected
+        );
+    }
+
+    Ok(match expected {
+        MapType::Hash => Map::HashMap(data),
+        MapType::Array => Map::Array(data),
+        MapType::PerCpuArray => Map::PerCpuArray(data),
+        MapType::LpmTrie => Map::LpmTrie(data),
+        _ => unreachable!("unsupported pinned map type {expected:?}"),
+    })
+}
+
+// 哈希 Map 与 LPM Trie 在内核中的容量 (max_entries)
+pub fn map_capacity(map: &Map) -> anyhow::Result<u32> {
+    match map {
+        Map::HashMap(data) | Map::LruHashMap(data) | Map::LpmTrie(data) => {
+            Ok(data.info()?.max_entries())
+        }
+        _ => bail!("not a hash map or LPM trie"),
+    }
+}
+
+// 转换为具体类型，键/值大小不一致时报错（例如固定 Map 来自不同版本的程序）
+pub fn typed<T>(map: Map, name: &str) -> anyhow::Result<T>
+where
+    T: TryFrom<Map, Error = aya::maps::MapError>,
+{
+    T::try_from(map).map_err(|e| anyhow!("pinned map {name} does not match this build: {e}"))
+}
@@ -0,0 +1,22 @@
+use tc_common::{PeerRegisters, PEER_REGISTERS};
+
+// HyperLogLog 中 m = 64 时的修正系数
+const ALPHA_64: f64 = 0.709;
+
+// 由 PEER_STATS 的寄存器估算不同远端地址数，标准误差约 13%
+pub fn estimate_distinct_peers(peers: &PeerRegisters) -> u64 {
+    let m = PEER_REGISTERS as f64;
+    let sum: f64 = peers
+        .registers
+        .iter()
+        .map(|&rank| 1.0 / (1u64 << rank) as f64)
+        .sum();
+    let estimate = ALPHA_64 * m * m / sum;
+
+    // 基数较小时改用线性计数
+    let zeros = peers.registers.iter().filter(|&&rank| rank == 0).count();
+    if estimate <= 2.5 * m && zeros > 0 {
+        return (m * (m / zeros as f64).ln()).round() as u64;
+    }
+    estimate.round() as u64
+}
@@ -0,0 +1,306 @@
+use std::fs;
+use std::io::Write;
+use std::mem::size_of;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context};
+use aya::maps::{HashMap, PerCpuArray, PerCpuValues};
+use aya::util::nr_cpus;
+use aya::{Ebpf, Pod};
+use tc_common::{Diagnostics, PeerRegisters, ProtocolStats, TrafficStats, MAX_PROTOCOLS};
+
+use crate::maps::StatsMaps;
+
+// 状态文件头部：魔数 + 版本 + 各结构体大小，任一不符即拒绝恢复
+const MAGIC: &[u8; 8] = b"TCSTATE\0";
+const VERSION: u32 = 2;
+
+// 保存时的 TRAFFIC_STATS / PEER_STATS / DIAGNOSTICS / PROTOCOL_STATS 内容
+pub struct MapState {
+    pub traffic: Vec<(u32, TrafficStats)>,
+    pub peers: Vec<(u32, PeerRegisters)>,
+    pub diagnostics: Diagnostics,
+    // 按协议号下标的各 CPU 合计值，共 MAX_PROTOCOLS 项
+    pub protocols: Vec<ProtocolStats>,
+}
+
+fn header() -> Vec<u8> {
+    let mut header = MAGIC.to_vec();
+    for value in [
+        VERSION,
+        size_of::<TrafficStats>() as u32,
+        size_of::<PeerRegisters>() as u32,
+        size_of::<Diagnostics>() as u32,
+        size_of::<ProtocolStats>() as u32,
+        MAX_PROTOCOLS,
+    ] {
+        header.extend_from_slice(&value.to_le_bytes());
+    }
+    header
+}
+
+fn push_pod<T: Pod>(buf: &mut Vec<u8>, value: &T) {
+    let bytes =
+        unsafe { std::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) };
+    buf.extend_from_slice(bytes);
+}
+
+fn push_entries<T: Pod>(buf: &mut Vec<u8>, entries: &[(u32, T)]) {
+    buf.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    for (key, value) in entries {
+        buf.extend_from_slice(&key.to_le_bytes());
+        push_pod(buf, value);
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> anyhow::Result<&[u8]> {
+        if self.data.len() < len {
+            bail!("state file is truncated");
+        }
+        let (head, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn pod<T: Pod>(&mut self) -> anyhow::Result<T> {
+        let bytes = self.take(size_of::<T>())?;
+        Ok(unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const T) })
+    }
+
+    fn entries<T: Pod>(&mut self) -> anyhow::Result<Vec<(u32, T)>> {
+        let count = self.u32()?;
+        (0..count).map(|_| Ok((self.u32()?, self.pod()?))).collect()
+    }
+}
+
+impl MapState {
+    pub fn read_maps(maps: &StatsMaps) -> anyhow::Result<Self> {
+        let mut total = Diagnostics::default();
+        for per_cpu in maps.diagnostics.get(&0, 0)?.iter() {
+            total.add(per_cpu);
+        }
+
+        let mut protocols = Vec::new();
+        for protocol in 0..MAX_PROTOCOLS {
+            let mut sum = ProtocolStats::default();
+            for per_cpu in maps.protocol_stats.get(&protocol, 0)?.iter() {
+                sum.packets += per_cpu.packets;
+                sum.bytes += per_cpu.bytes;
+            }
+            protocols.push(sum);
+        }
+
+        Ok(Self {
+            traffic: maps.traffic.iter().collect::<Result<_, _>>()?,
+            peers: maps.peer_stats.iter().collect::<Result<_, _>>()?,
+            diagnostics: total,
+            protocols,
+        })
+    }
+
+    // 先写临时文件再改名，进程中途退出不会留下不完整的状态文件
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let mut buf = header();
+        push_entries(&mut buf, &self.traffic);
+        push_entries(&mut buf, &self.peers);
+        push_pod(&mut buf, &self.diagnostics);
+        for protocol in &self.protocols {
+            push_pod(&mut buf, protocol);
+        }
+
+        let tmp_path = path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp_path)
+            .with_context(|| format!("failed to create {}", tmp_path.display()))?;
+        file.write_all(&buf)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let data = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let mut reader = Reader { data: &data };
+
+        let expected = header();
+        if reader.take(expected.len()).ok() != Some(expected.as_slice()) {
+            bail!(
+                "{} was saved by an incompatible version (header mismatch)",
+                path.display()
+            );
+        }
+
+        let state = Self {
+            traffic: reader.entries()?,
+            peers: reader.entries()?,
+            diagnostics: reader.pod()?,
+            protocols: (0..MAX_PROTOCOLS)
+                .map(|_| reader.pod())
+                .collect::<anyhow::Result<_>>()?,
+        };
+        if !reader.data.is_empty() {
+            bail!("{} has trailing data", path.display());
+        }
+
+        Ok(state)
+    }
+
+    pub fn restore(&self, bpf: &mut Ebpf) -> anyhow::Result<()> {
+        let mut traffic_map: HashMap<_, u32, TrafficStats> =
+            HashMap::try_from(bpf.map_mut("TRAFFIC_STATS").unwrap())?;
+        for (ip, stats) in &self.traffic {
+            // 时间戳与瞬时速率只在本次运行内有意义，恢复时清零，由新数据包重新计算
+            let stats = TrafficStats {
+                last_seen_ns: 0,
+                byte_rate_ewma: 0,
+                ..*stats
+            };
+            traffic_map.insert(ip, stats, 0)?;
+        }
+
+        let mut peer_stats: HashMap<_, u32, PeerRegisters> =
+            HashMap::try_from(bpf.map_mut("PEER_STATS").unwrap())?;
+        for (ip, peers) in &self.peers {
+            peer_stats.insert(ip, peers, 0)?;
+        }
+
+        // 诊断计数器与协议统计按 CPU 存放，合计值写入第一个 CPU
+        let cpus = nr_cpus().map_err(|(msg, e)| anyhow!("{msg}: {e}"))?;
+        let mut diagnostics: PerCpuArray<_, Diagnostics> =
+            PerCpuArray::try_from(bpf.map_mut("DIAGNOSTICS").unwrap())?;
+        let mut values = vec![Diagnostics::default(); cpus];
+        values[0] = self.diagnostics;
+        diagnostics.set(0, PerCpuValues::try_from(values)?, 0)?;
+
+        let mut protocol_stats: PerCpuArray<_, ProtocolStats> =
+            PerCpuArray::try_from(bpf.map_mut("PROTOCOL_STATS").unwrap())?;
+        for (protocol, stats) in self.protocols.iter().enumerate() {
+            if stats.packets == 0 {
+                continue;
+            }
+            let mut values = vec![ProtocolStats::default(); cpus];
+            values[0] = *stats;
+            protocol_stats.set(protocol as u32, PerCpuValues::try_from(values)?, 0)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // 每个测试使用独立的临时文件，避免并行测试互相覆盖
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("tc-state-{}-{name}.bin", std::process::id()))
+    }
+
+    fn sample_state() -> MapState {
+        let mut stats = TrafficStats::new();
+        stats.inbound_packets = 3;
+        stats.inbound_bytes = 1500;
+        stats.outbound_packets = 1;
+        stats.outbound_bytes = 60;
+        stats.gap_buckets = [1, 2, 0, 0];
+
+        let mut peers = PeerRegisters {
+            registers: [0; tc_common::PEER_REGISTERS],
+        };
+        peers.registers[5] = 7;
+
+        MapState {
+            traffic: vec![(0x0a00_0001, stats)],
+            peers: vec![(0x0a00_0001, peers)],
+            diagnostics: Diagnostics {
+                fragmented_packets: 2,
+                malformed_packets: 1,
+                ..Default::default()
+            },
+            protocols: (0..MAX_PROTOCOLS)
+                .map(|protocol| ProtocolStats {
+                    packets: protocol as u64,
+                    bytes: protocol as u64 * 100,
+                })
+                .collect(),
+        }
+    }
+
+    fn save_bytes(name: &str) -> (PathBuf, Vec<u8>) {
+        let path = temp_path(name);
+        sample_state().save(&path).unwrap();
+        let data = fs::read(&path).unwrap();
+        (path, data)
+    }
+
+    fn load_err(path: &Path) -> String {
+        let err = MapState::load(path).err().expect("load should fail");
+        let _ = fs::remove_file(path);
+        format!("{err:#}")
+    }
+
+    #[test]
+    fn save_then_load_round_trips() {
+        let path = temp_path("round-trip");
+        sample_state().save(&path).unwrap();
+        let loaded = MapState::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.traffic.len(), 1);
+        let (ip, stats) = loaded.traffic[0];
+        assert_eq!(ip, 0x0a00_0001);
+        assert_eq!(stats.inbound_packets, 3);
+        assert_eq!(stats.inbound_bytes, 1500);
+        assert_eq!(stats.outbound_packets, 1);
+        assert_eq!(stats.outbound_bytes, 60);
+        assert_eq!(stats.gap_buckets, [1, 2, 0, 0]);
+
+        assert_eq!(loaded.peers.len(), 1);
+        assert_eq!(loaded.peers[0].0, 0x0a00_0001);
+        assert_eq!(loaded.peers[0].1.registers[5], 7);
+
+        assert_eq!(loaded.diagnostics.fragmented_packets, 2);
+        assert_eq!(loaded.diagnostics.malformed_packets, 1);
+
+        assert_eq!(loaded.protocols.len(), MAX_PROTOCOLS as usize);
+        assert_eq!(loaded.protocols[6].packets, 6);
+        assert_eq!(loaded.protocols[17].bytes, 1700);
+    }
+
+    #[test]
+    fn load_rejects_header_mismatch() {
+        let (path, mut data) = save_bytes("header");
+        // 版本号紧跟在魔数之后
+        data[MAGIC.len()] ^= 0xff;
+        fs::write(&path, &data).unwrap();
+
+        assert!(load_err(&path).contains("header mismatch"));
+    }
+
+    #[test]
+    fn load_rejects_truncated_file() {
+        let (path, data) = save_bytes("truncated");
+        fs::write(&path, &data[..data.len() - 1]).unwrap();
+
+        assert!(load_err(&path).contains("truncated"));
+    }
+
+    #[test]
+    fn load_rejects_trailing_data() {
+        let (path, mut data) = save_bytes("trailing");
+        data.push(0);
+        fs::write(&path, &data).unwrap();
+
+        assert!(load_err(&path).contains("trailing data"));
+    }
+}
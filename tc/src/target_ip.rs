@@ -1,35 +1,46 @@
 use std::env;
+use std::fmt;
+use std::net::{IpAddr, Ipv6Addr};
 
+use anyhow::Context;
 use tc_common::utils::ip_to_u32;
 
 use crate::utils::u32_to_ip;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct TargetIp(pub u32);
 
-impl TargetIp {
-    pub fn to_string(&self) -> String {
-        u32_to_ip(self.0).to_string()
+impl fmt::Display for TargetIp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", u32_to_ip(self.0))
     }
 }
 
-pub fn get_target_ip() -> anyhow::Result<Vec<TargetIp>> {
-    let target_ip_str = env::var("TARGET_IP").unwrap_or_default();
-
-    let target_ip: Vec<&str> = target_ip_str.split(',').collect();
+// TARGET_IP 的解析结果。XDP 程序目前只解析 IPv4，IPv6 条目单独列出，由调用方提示后忽略
+#[derive(Debug, Default)]
+pub struct TargetIps {
+    pub ipv4: Vec<TargetIp>,
+    pub ipv6: Vec<Ipv6Addr>,
+}
 
-    let target_ip_u32: Vec<u32> = target_ip
-        .iter()
-        .map(|ip| {
-            ip_to_u32(
-                ip.split('.')
-                    .map(|s| s.parse().unwrap())
-                    .collect::<Vec<u8>>()
-                    .try_into()
-                    .unwrap(),
-            )
-        })
-        .collect();
+pub fn parse_target_ip(target_ip_str: &str) -> anyhow::Result<TargetIps> {
+    let mut target_ips = TargetIps::default();
+    for ip in target_ip_str
+        .split(',')
+        .map(|ip| ip.trim())
+        .filter(|ip| !ip.is_empty())
+    {
+        match ip
+            .parse::<IpAddr>()
+            .with_context(|| format!("invalid TARGET_IP entry: {ip}"))?
+        {
+            IpAddr::V4(ip) => target_ips.ipv4.push(TargetIp(ip_to_u32(ip.octets()))),
+            IpAddr::V6(ip) => target_ips.ipv6.push(ip),
+        }
+    }
+    Ok(target_ips)
+}
 
-    Ok(target_ip_u32.iter().map(|ip| TargetIp(*ip)).collect())
+pub fn get_target_ip() -> anyhow::Result<TargetIps> {
+    parse_target_ip(&env::var("TARGET_IP").unwrap_or_default())
 }
@@ -0,0 +1,35 @@
+use std::collections::HashMap;
+use std::env;
+use std::net::Ipv4Addr;
+
+use anyhow::{anyhow, Context};
+use tc_common::utils::ip_to_u32;
+
+// TARGET_LABEL=10.0.0.5:db-primary,10.0.0.6:edge-lb，仅用于展示，不写入 eBPF Map
+pub fn parse_target_label(target_label_str: &str) -> anyhow::Result<HashMap<u32, String>> {
+    target_label_str
+        .split(',')
+        .map(|entry| entry.trim())
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (ip, label) = entry
+                .split_once(':')
+                .ok_or_else(|| anyhow!("invalid TARGET_LABEL entry: {entry}, expected ip:label"))?;
+            let ip = ip
+                .trim()
+                .parse::<Ipv4Addr>()
+                .with_context(|| format!("invalid TARGET_LABEL entry: {entry}"))?;
+            let label = label.trim();
+            if label.is_empty() {
+                return Err(anyhow!(
+                    "invalid TARGET_LABEL entry: {entry}, label is empty"
+                ));
+            }
+            Ok((ip_to_u32(ip.octets()), label.to_string()))
+        })
+        .collect()
+}
+
+pub fn get_target_label() -> anyhow::Result<HashMap<u32, String>> {
+    parse_target_label(&env::var("TARGET_LABEL").unwrap_or_default())
+}
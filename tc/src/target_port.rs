@@ -0,0 +1,138 @@
+use std::env;
+use std::fmt;
+
+use anyhow::{anyhow, Context};
+use tc_common::{PortRange, MAX_PORT_RANGES};
+
+// TARGET_PORT 中的单个端口与端口范围，例如 80,443,8000-8100
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TargetPorts {
+    pub ports: Vec<u16>,
+    pub ranges: Vec<PortRange>,
+}
+
+impl TargetPorts {
+    pub fn is_empty(&self) -> bool {
+        self.ports.is_empty() && self.ranges.is_empty()
+    }
+}
+
+impl fmt::Display for TargetPorts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entries: Vec<String> = self
+            .ports
+            .iter()
+            .map(|port| port.to_string())
+            .chain(
+                self.ranges
+                    .iter()
+                    .map(|range| format!("{}-{}", range.start, range.end)),
+            )
+            .collect();
+        write!(f, "{}", entries.join(", "))
+    }
+}
+
+fn parse_port(port: &str, entry: &str) -> anyhow::Result<u16> {
+    port.trim()
+        .parse::<u16>()
+        .with_context(|| format!("invalid TARGET_PORT entry: {entry}"))
+}
+
+pub fn parse_target_port(target_port_str: &str) -> anyhow::Result<TargetPorts> {
+    let mut target_ports = TargetPorts::default();
+    for entry in target_port_str
+        .split(',')
+        .map(|entry| entry.trim())
+        .filter(|entry| !entry.is_empty())
+    {
+        let Some((start, end)) = entry.split_once('-') else {
+            target_ports.ports.push(parse_port(entry, entry)?);
+            continue;
+        };
+        let (start, end) = (parse_port(start, entry)?, parse_port(end, entry)?);
+        // end 为 0 表示 PORT_RANGES 中的空槽位，因此范围不能以 0 结尾
+        if start > end || end == 0 {
+            return Err(anyhow!("invalid TARGET_PORT range: {entry}"));
+        }
+        target_ports.ranges.push(PortRange { start, end });
+    }
+
+    if target_ports.ranges.len() > MAX_PORT_RANGES {
+        return Err(anyhow!(
+            "too many TARGET_PORT ranges: {} (max {MAX_PORT_RANGES})",
+            target_ports.ranges.len()
+        ));
+    }
+
+    Ok(target_ports)
+}
+
+pub fn get_target_port() -> anyhow::Result<TargetPorts> {
+    parse_target_port(&env::var("TARGET_PORT").unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: u16, end: u16) -> PortRange {
+        PortRange { start, end }
+    }
+
+    #[test]
+    fn parses_mixed_ports_and_ranges() {
+        let ports = parse_target_port(" 80 , 8000 - 8100,443,, 9000-9000 ").unwrap();
+        assert_eq!(ports.ports, vec![80, 443]);
+        assert_eq!(ports.ranges, vec![range(8000, 8100), range(9000, 9000)]);
+        assert_eq!(ports.to_string(), "80, 443, 8000-8100, 9000-9000");
+    }
+
+    #[test]
+    fn empty_input_has_no_ports() {
+        assert!(parse_target_port("").unwrap().is_empty());
+        assert!(parse_target_port(" , ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_reversed_range() {
+        let err = parse_target_port("8100-8000").unwrap_err().to_string();
+        assert!(err.contains("invalid TARGET_PORT range"));
+    }
+
+    #[test]
+    fn handles_port_zero() {
+        // 单个端口 0 与以 0 开始的范围合法，以 0 结尾的范围会与空槽位混淆
+        assert_eq!(parse_target_port("0").unwrap().ports, vec![0]);
+        assert_eq!(
+            parse_target_port("0-10").unwrap().ranges,
+            vec![range(0, 10)]
+        );
+        assert!(parse_target_port("0-0").is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_entries() {
+        assert!(parse_target_port("http").is_err());
+        assert!(parse_target_port("65536").is_err());
+        assert!(parse_target_port("80-").is_err());
+        assert!(parse_target_port("1-2-3").is_err());
+    }
+
+    #[test]
+    fn limits_range_count() {
+        let ranges = |count: u16| {
+            (0..count)
+                .map(|i| format!("{}-{}", 1000 + i * 10, 1005 + i * 10))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let max = MAX_PORT_RANGES as u16;
+        assert_eq!(
+            parse_target_port(&ranges(max)).unwrap().ranges.len(),
+            MAX_PORT_RANGES
+        );
+        let err = parse_target_port(&ranges(max + 1)).unwrap_err().to_string();
+        assert!(err.contains("too many TARGET_PORT ranges"));
+    }
+}
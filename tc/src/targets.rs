@@ -0,0 +1,389 @@
+use std::collections::{HashMap as StdHashMap, HashSet};
+use std::path::Path;
+
+use anyhow::bail;
+use aya::maps::lpm_trie::{Key, LpmTrie};
+use aya::maps::{Array, HashMap, MapData, MapType};
+use aya::Ebpf;
+use tc_common::{PortRange, MAX_PORT_RANGES};
+
+use crate::exclude_ip::ExcludeNet;
+use crate::maps::{map_capacity, open_pinned, typed};
+use crate::target_ip::TargetIp;
+use crate::target_port::TargetPorts;
+
+// 去重后的条目数超出内核 Map 容量时报错，启动、重新加载与 check-config 共用
+pub fn check_capacity(name: &str, entries: usize, capacity: u32) -> anyhow::Result<()> {
+    if entries > capacity as usize {
+        bail!("{name} has {entries} entries, but the kernel map holds at most {capacity}");
+    }
+    Ok(())
+}
+
+// 一次更新中 TARGET_IP / TARGET_PORT / PORT_FILTER / EXCLUDE_IP 的变化
+#[derive(Debug, Default)]
+pub struct TargetChanges {
+    pub added_ips: Vec<TargetIp>,
+    pub removed_ips: Vec<TargetIp>,
+    pub added_ports: Vec<u16>,
+    pub removed_ports: Vec<u16>,
+    pub ranges_changed: bool,
+    pub port_filter_changed: bool,
+    pub added_excludes: Vec<ExcludeNet>,
+    pub removed_excludes: Vec<ExcludeNet>,
+}
+
+impl TargetChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added_ips.is_empty()
+            && self.removed_ips.is_empty()
+            && self.added_ports.is_empty()
+            && self.removed_ports.is_empty()
+            && !self.ranges_changed
+            && !self.port_filter_changed
+            && self.added_excludes.is_empty()
+            && self.removed_excludes.is_empty()
+    }
+}
+
+// 内核中目标 Map 与当前配置的差异，由 reconcile 修复
+#[derive(Debug, Default)]
+pub struct TargetDrift {
+    pub missing_ips: Vec<TargetIp>,
+    pub unexpected_ips: Vec<TargetIp>,
+    pub missing_ports: Vec<u16>,
+    pub unexpected_ports: Vec<u16>,
+    pub missing_excludes: Vec<ExcludeNet>,
+    pub unexpected_excludes: Vec<ExcludeNet>,
+    pub ranges_rewritten: bool,
+    pub port_filter_rewritten: bool,
+}
+
+impl TargetDrift {
+    pub fn is_empty(&self) -> bool {
+        self.missing_ips.is_empty()
+            && self.unexpected_ips.is_empty()
+            && self.missing_ports.is_empty()
+            && self.unexpected_ports.is_empty()
+            && self.missing_excludes.is_empty()
+            && self.unexpected_excludes.is_empty()
+            && !self.ranges_rewritten
+            && !self.port_filter_rewritten
+    }
+}
+
+// 持有目标相关的 eBPF Map 以及当前生效的配置，启动与重新加载共用
+pub struct Targets {
+    ip_map: HashMap<MapData, u32, u8>,
+    port_map: HashMap<MapData, u16, u8>,
+    port_range_map: Array<MapData, PortRange>,
+    port_filter_map: Array<MapData, u32>,
+    exclude_map: LpmTrie<MapData, u32, u8>,
+    // TARGET_IP / TARGET_PORT / EXCLUDE_IP 的 max_entries，超出的条目在内核中无法插入
+    ip_capacity: u32,
+    port_capacity: u32,
+    exclude_capacity: u32,
+    pub ips: Vec<TargetIp>,
+    pub ports: TargetPorts,
+    pub port_filter: u32,
+    pub excludes: Vec<ExcludeNet>,
+    // IP -> 展示用标签
+    pub labels: StdHashMap<u32, String>,
+}
+
+impl Targets {
+    pub fn new(bpf: &mut Ebpf) -> anyhow::Result<Self> {
+        let ip_map = bpf.take_map("TARGET_IP").unwrap();
+        let port_map = bpf.take_map("TARGET_PORT").unwrap();
+        let exclude_map = bpf.take_map("EXCLUDE_IP").unwrap();
+        Ok(Self {
+            ip_capacity: map_capacity(&ip_map)?,
+            port_capacity: map_capacity(&port_map)?,
+            exclude_capacity: map_capacity(&exclude_map)?,
+            ip_map: HashMap::try_from(ip_map)?,
+            port_map: HashMap::try_from(port_map)?,
+            port_range_map: Array::try_from(bpf.take_map("PORT_RANGES").unwrap())?,
+            port_filter_map: Array::try_from(bpf.take_map("PORT_FILTER").unwrap())?,
+            exclude_map: LpmTrie::try_from(exclude_map)?,
+            ips: Vec::new(),
+            ports: TargetPorts::default(),
+            port_filter: 0,
+            excludes: Vec::new(),
+            labels: StdHashMap::new(),
+        })
+    }
+
+    // 打开其他进程固定的目标 Map，并读出其中已生效的配置
+    pub fn from_pins(dir: &Path) -> anyhow::Result<Self> {
+        let ip_map = open_pinned(dir, "TARGET_IP", MapType::Hash)?;
+        let port_map = open_pinned(dir, "TARGET_PORT", MapType::Hash)?;
+        let exclude_map = open_pinned(dir, "EXCLUDE_IP", MapType::LpmTrie)?;
+        let mut targets = Self {
+            ip_capacity: map_capacity(&ip_map)?,
+            port_capacity: map_capacity(&port_map)?,
+            exclude_capacity: map_capacity(&exclude_map)?,
+            ip_map: typed(ip_map, "TARGET_IP")?,
+            port_map: typed(port_map, "TARGET_PORT")?,
+            port_range_map: typed(
+                open_pinned(dir, "PORT_RANGES", MapType::Array)?,
+                "PORT_RANGES",
+            )?,
+            port_filter_map: typed(
+                open_pinned(dir, "PORT_FILTER", MapType::Array)?,
+                "PORT_FILTER",
+            )?,
+            exclude_map: typed(exclude_map, "EXCLUDE_IP")?,
+            ips: Vec::new(),
+            ports: TargetPorts::default(),
+            port_filter: 0,
+            excludes: Vec::new(),
+            labels: StdHashMap::new(),
+        };
+
+        for ip in targets.ip_map.keys() {
+            targets.ips.push(TargetIp(ip?));
+        }
+        for port in targets.port_map.keys() {
+            targets.ports.ports.push(port?);
+        }
+        for index in 0..MAX_PORT_RANGES {
+            let range = targets.port_range_map.get(&(index as u32), 0)?;
+            if range.end == 0 {
+                break;
+            }
+            targets.ports.ranges.push(range);
+        }
+        targets.port_filter = targets.port_filter_map.get(&0, 0)?;
+        targets.excludes = targets.map_excludes()?;
+
+        Ok(targets)
+    }
+
+    // 只增删有变化的条目，未变化的 IP 在更新期间一直保持生效
+    pub fn apply(
+        &mut self,
+        ips: Vec<TargetIp>,
+        ports: TargetPorts,
+        port_filter: u32,
+    ) -> anyhow::Result<TargetChanges> {
+        let mut changes = TargetChanges::default();
+
+        let old_ips: HashSet<TargetIp> = self.ips.iter().copied().collect();
+        let new_ips: HashSet<TargetIp> = ips.iter().copied().collect();
+        let old_ports: HashSet<u16> = self.ports.ports.iter().copied().collect();
+        let new_ports: HashSet<u16> = ports.ports.iter().copied().collect();
+        check_capacity("TARGET_IP", new_ips.len(), self.ip_capacity)?;
+        check_capacity("TARGET_PORT", new_ports.len(), self.port_capacity)?;
+
+        // 新旧条目合计超出容量时先删除旧条目腾出空间，否则照常在插入之后删除
+        let remove_ips_first = old_ips.union(&new_ips).count() > self.ip_capacity as usize;
+        let remove_ports_first = old_ports.union(&new_ports).count() > self.port_capacity as usize;
+        if remove_ips_first {
+            self.remove_stale_ips(&new_ips, &mut changes)?;
+        }
+        if remove_ports_first {
+            self.remove_stale_ports(&new_ports, &mut changes)?;
+        }
+
+        for ip in ips.iter().filter(|ip| !old_ips.contains(ip)) {
+            self.ip_map.insert(ip.0, 1u8, 0)?;
+            changes.added_ips.push(*ip);
+        }
+
+        for port in ports.ports.iter().filter(|port| !old_ports.contains(port)) {
+            self.port_map.insert(port, 1u8, 0)?;
+            changes.added_ports.push(*port);
+        }
+
+        // 范围列表很短，有变化时整体重写，多余槽位清零
+        if ports.ranges != self.ports.ranges {
+            for index in 0..MAX_PORT_RANGES {
+                let range = ports.ranges.get(index).copied().unwrap_or_default();
+                self.port_range_map.set(index as u32, range, 0)?;
+            }
+            changes.ranges_changed = true;
+        }
+
+        // 先放宽过滤再收紧：新条目已插入后才切换策略，旧条目在切换后才删除
+        if port_filter != self.port_filter {
+            self.port_filter_map.set(0, port_filter, 0)?;
+            changes.port_filter_changed = true;
+        }
+
+        if !remove_ips_first {
+            self.remove_stale_ips(&new_ips, &mut changes)?;
+        }
+        if !remove_ports_first {
+            self.remove_stale_ports(&new_ports, &mut changes)?;
+        }
+
+        self.ips = ips;
+        self.ports = ports;
+        self.port_filter = port_filter;
+
+        Ok(changes)
+    }
+
+    // 重新加载时应在 apply 之前调用，避免目标已更新而排除网段因容量不足被拒绝
+    pub fn check_exclude_capacity(&self, excludes: &[ExcludeNet]) -> anyhow::Result<()> {
+        let distinct: HashSet<&ExcludeNet> = excludes.iter().collect();
+        check_capacity("EXCLUDE_IP", distinct.len(), self.exclude_capacity)
+    }
+
+    // 先插入新的排除网段再删除旧的，更新期间原本排除的地址不会被短暂统计；
+    // 新旧合计超出容量时先删除旧网段腾出空间
+    pub fn apply_excludes(
+        &mut self,
+        excludes: Vec<ExcludeNet>,
+        changes: &mut TargetChanges,
+    ) -> anyhow::Result<()> {
+        self.check_exclude_capacity(&excludes)?;
+        let old: HashSet<ExcludeNet> = self.excludes.iter().copied().collect();
+        let new: HashSet<ExcludeNet> = excludes.iter().copied().collect();
+        let remove_first = old.union(&new).count() > self.exclude_capacity as usize;
+        if remove_first {
+            self.remove_stale_excludes(&new, changes)?;
+        }
+
+        for net in excludes.iter().filter(|net| !old.contains(net)) {
+            self.add_exclude_ip(*net)?;
+            changes.added_excludes.push(*net);
+        }
+        if !remove_first {
+            self.remove_stale_excludes(&new, changes)?;
+        }
+
+        self.excludes = excludes;
+        Ok(())
+    }
+
+    fn remove_stale_excludes(
+        &mut self,
+        new: &HashSet<ExcludeNet>,
+        changes: &mut TargetChanges,
+    ) -> anyhow::Result<()> {
+        let stale: Vec<ExcludeNet> = self
+            .excludes
+            .iter()
+            .filter(|net| !new.contains(net))
+            .copied()
+            .collect();
+        for net in stale {
+            self.remove_exclude_ip(net)?;
+            changes.removed_excludes.push(net);
+        }
+        Ok(())
+    }
+
+    // 内核 EXCLUDE_IP 中当前的全部网段
+    fn map_excludes(&self) -> anyhow::Result<Vec<ExcludeNet>> {
+        let mut excludes = Vec::new();
+        for key in self.exclude_map.keys() {
+            let key = key?;
+            excludes.push(ExcludeNet {
+                addr: u32::from_be(key.data()),
+                prefix_len: key.prefix_len() as u8,
+            });
+        }
+        Ok(excludes)
+    }
+
+    // LPM Key 的数据部分按网络字节序比较前缀
+    pub fn add_exclude_ip(&mut self, net: ExcludeNet) -> anyhow::Result<()> {
+        let key = Key::new(net.prefix_len as u32, net.addr.to_be());
+        self.exclude_map.insert(&key, 1u8, 0)?;
+        Ok(())
+    }
+
+    pub fn remove_exclude_ip(&mut self, net: ExcludeNet) -> anyhow::Result<()> {
+        let key = Key::new(net.prefix_len as u32, net.addr.to_be());
+        self.exclude_map.remove(&key)?;
+        Ok(())
+    }
+
+    // 对照当前配置检查内核中的目标 Map，补回缺失的条目并删除多出的条目
+    pub fn reconcile(&mut self) -> anyhow::Result<TargetDrift> {
+        let mut drift = TargetDrift::default();
+
+        let ips: HashSet<TargetIp> = self.ips.iter().copied().collect();
+        let map_ips = self
+            .ip_map
+            .keys()
+            .map(|ip| ip.map(TargetIp))
+            .collect::<Result<HashSet<_>, _>>()?;
+        for ip in map_ips.difference(&ips) {
+            self.ip_map.remove(&ip.0)?;
+            drift.unexpected_ips.push(*ip);
+        }
+        for ip in ips.difference(&map_ips) {
+            self.ip_map.insert(ip.0, 1u8, 0)?;
+            drift.missing_ips.push(*ip);
+        }
+
+        let ports: HashSet<u16> = self.ports.ports.iter().copied().collect();
+        let map_ports = self.port_map.keys().collect::<Result<HashSet<_>, _>>()?;
+        for port in map_ports.difference(&ports) {
+            self.port_map.remove(port)?;
+            drift.unexpected_ports.push(*port);
+        }
+        for port in ports.difference(&map_ports) {
+            self.port_map.insert(port, 1u8, 0)?;
+            drift.missing_ports.push(*port);
+        }
+
+        for index in 0..MAX_PORT_RANGES {
+            let range = self.ports.ranges.get(index).copied().unwrap_or_default();
+            if self.port_range_map.get(&(index as u32), 0)? != range {
+                self.port_range_map.set(index as u32, range, 0)?;
+                drift.ranges_rewritten = true;
+            }
+        }
+
+        let excludes: HashSet<ExcludeNet> = self.excludes.iter().copied().collect();
+        let map_excludes: HashSet<ExcludeNet> = self.map_excludes()?.into_iter().collect();
+        for net in map_excludes.difference(&excludes) {
+            self.remove_exclude_ip(*net)?;
+            drift.unexpected_excludes.push(*net);
+        }
+        for net in excludes.difference(&map_excludes) {
+            self.add_exclude_ip(*net)?;
+            drift.missing_excludes.push(*net);
+        }
+
+        if self.port_filter_map.get(&0, 0)? != self.port_filter {
+            self.port_filter_map.set(0, self.port_filter, 0)?;
+            drift.port_filter_rewritten = true;
+        }
+
+        Ok(drift)
+    }
+
+    fn remove_stale_ips(
+        &mut self,
+        new_ips: &HashSet<TargetIp>,
+        changes: &mut TargetChanges,
+    ) -> anyhow::Result<()> {
+        for ip in self.ips.iter().filter(|ip| !new_ips.contains(ip)) {
+            self.ip_map.remove(&ip.0)?;
+            changes.removed_ips.push(*ip);
+        }
+        Ok(())
+    }
+
+    fn remove_stale_ports(
+        &mut self,
+        new_ports: &HashSet<u16>,
+        changes: &mut TargetChanges,
+    ) -> anyhow::Result<()> {
+        for port in self
+            .ports
+            .ports
+            .iter()
+            .filter(|port| !new_ports.contains(port))
+        {
+            self.port_map.remove(port)?;
+            changes.removed_ports.push(*port);
+        }
+        Ok(())
+    }
+}
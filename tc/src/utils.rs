@@ -1,5 +1,56 @@
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use tc_common::packet::{IPPROTO_SCTP, IPPROTO_TCP, IPPROTO_UDP};
 
 pub fn u32_to_ip(ip: u32) -> Ipv4Addr {
     Ipv4Addr::from(ip)
 }
+
+// 保留前 prefix_len 位，其余主机位清零
+pub fn anonymize_ip(ip: Ipv4Addr, prefix_len: u8) -> Ipv4Addr {
+    let mask = u32::MAX
+        .checked_shl(32 - u32::from(prefix_len.min(32)))
+        .unwrap_or(0);
+    Ipv4Addr::from(u32::from(ip) & mask)
+}
+
+// IPv6 同样只保留前 prefix_len 位
+pub fn anonymize_ipv6(ip: Ipv6Addr, prefix_len: u8) -> Ipv6Addr {
+    let mask = u128::MAX
+        .checked_shl(128 - u32::from(prefix_len.min(128)))
+        .unwrap_or(0);
+    Ipv6Addr::from(u128::from(ip) & mask)
+}
+
+// 常见 IP 协议号的名称，未收录的协议显示协议号
+pub fn protocol_name(protocol: u8) -> String {
+    let name = match protocol {
+        1 => "ICMP",
+        IPPROTO_TCP => "TCP",
+        IPPROTO_UDP => "UDP",
+        47 => "GRE",
+        50 => "ESP",
+        51 => "AH",
+        IPPROTO_SCTP => "SCTP",
+        _ => return format!("proto {protocol}"),
+    };
+    name.to_string()
+}
+
+// CLOCK_MONOTONIC 纳秒时间，与 eBPF 中的 bpf_ktime_get_ns 可比
+pub fn monotonic_ns() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+// part 占 total 的百分比，total 为 0 时返回 0
+pub fn percent_of(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    part as f64 * 100.0 / total as f64
+}